@@ -0,0 +1,103 @@
+//! Fallback terminal without any formatting capabilities
+
+use std::io;
+use std::io::prelude::*;
+
+use crate::color;
+use crate::Attr;
+use crate::Result;
+use crate::Terminal;
+
+/// A Terminal that passes text through unchanged and ignores all formatting.
+///
+/// Color and attribute changes are silently dropped, so this can stand in for a real terminal
+/// when no terminal description is available (e.g. in CI). Cursor movement is not supported.
+#[derive(Clone, Debug)]
+pub struct DumbTerminal<T> {
+    out: T,
+}
+
+impl<T: Write> DumbTerminal<T> {
+    /// Create a new DumbTerminal writing to `out`.
+    pub fn new(out: T) -> DumbTerminal<T> {
+        DumbTerminal { out }
+    }
+}
+
+impl<T: Write> Terminal for DumbTerminal<T> {
+    type Output = T;
+
+    fn fg(&mut self, _color: color::Color) -> Result<()> {
+        Ok(())
+    }
+
+    fn bg(&mut self, _color: color::Color) -> Result<()> {
+        Ok(())
+    }
+
+    fn attr(&mut self, _attr: Attr) -> Result<()> {
+        Ok(())
+    }
+
+    fn supports_attr(&self, _attr: Attr) -> bool {
+        false
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn supports_reset(&self) -> bool {
+        false
+    }
+
+    fn supports_color(&self) -> bool {
+        false
+    }
+
+    fn cursor_up(&mut self) -> Result<()> {
+        Err(crate::Error::NotSupported)
+    }
+
+    fn delete_line(&mut self) -> Result<()> {
+        Err(crate::Error::NotSupported)
+    }
+
+    fn carriage_return(&mut self) -> Result<()> {
+        self.out.write_all(b"\r")?;
+        Ok(())
+    }
+
+    fn begin_sync_update(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_sync_update(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_ref(&self) -> &T {
+        &self.out
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        &mut self.out
+    }
+
+    fn into_inner(self) -> T
+    where
+        Self: Sized,
+    {
+        self.out
+    }
+}
+
+impl<T: Write> Write for DumbTerminal<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
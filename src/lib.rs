@@ -14,7 +14,8 @@
 //! Terminal][ansi] to provide color printing, among other things. There are two
 //! implementations, the `TerminfoTerminal`, which uses control characters from
 //! a [terminfo][ti] database, and `WinConsole`, which uses the [Win32 Console
-//! API][win].
+//! API][win]. When neither is available, `DumbTerminal` passes plain text
+//! through and ignores formatting.
 //!
 //! # Usage
 //!
@@ -59,14 +60,26 @@
 
 use std::io::prelude::*;
 
+pub use crate::dumb::DumbTerminal;
+pub use crate::shared::SharedWriter;
+pub use crate::style::{Style, StyledStr};
+pub use crate::tee::TeeTerminal;
 pub use crate::terminfo::TerminfoTerminal;
+pub use crate::width::display_width;
 #[cfg(windows)]
 pub use win::{WinConsole, WinConsoleInfo};
 
-use std::io::{self, Stderr, Stdout};
+use std::io::{self, IsTerminal, Stderr, Stdout};
 
+mod dumb;
+mod shared;
+mod style;
+mod tee;
 pub mod terminfo;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test;
 
+mod width;
 #[cfg(windows)]
 mod win;
 
@@ -115,6 +128,86 @@ pub fn stderr() -> Option<Box<StderrTerminal>> {
         })
 }
 
+/// Forgets the terminfo entry shared by `stdout`, `stderr` and `TerminfoTerminal::new`, so that
+/// the next of them reads it again.
+///
+/// The entry is looked up again anyway when `TERM` or the other variables that locate it change,
+/// so this is only needed when the terminfo database itself has changed.
+pub fn clear_terminfo_cache() {
+    terminfo::clear_cache();
+}
+
+/// Return a Terminal wrapping stdout that never emits formatting.
+///
+/// Unlike `stdout()`, this always succeeds, so it can be used as a fallback when no terminal could
+/// be detected.
+pub fn dumb_stdout() -> Box<StdoutTerminal> {
+    Box::new(DumbTerminal::new(io::stdout()))
+}
+
+/// Return a Terminal wrapping stderr that never emits formatting.
+///
+/// Unlike `stderr()`, this always succeeds, so it can be used as a fallback when no terminal could
+/// be detected.
+pub fn dumb_stderr() -> Box<StderrTerminal> {
+    Box::new(DumbTerminal::new(io::stderr()))
+}
+
+/// When to emit colors and other formatting, as commonly selected with a `--color` flag.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum ColorChoice {
+    /// Always format output, even when it isn't going to a terminal.
+    Always,
+    /// Format output if it's going to a terminal, unless disabled through the environment.
+    ///
+    /// Formatting is disabled if `NO_COLOR` is set to a non-empty value or `CLICOLOR` is `0`,
+    /// and forced (as with `Always`) if `CLICOLOR_FORCE` is set to anything but `0`.
+    Auto,
+    /// Never format output.
+    Never,
+}
+
+impl ColorChoice {
+    fn use_color(self, is_terminal: bool) -> bool {
+        let var = |name| std::env::var_os(name).filter(|v| !v.is_empty());
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                if var("CLICOLOR_FORCE").is_some_and(|v| v != "0") {
+                    true
+                } else if var("NO_COLOR").is_some() || var("CLICOLOR").is_some_and(|v| v == "0") {
+                    false
+                } else {
+                    is_terminal
+                }
+            }
+        }
+    }
+}
+
+/// Return a Terminal wrapping stdout that formats its output according to `choice`.
+///
+/// Falls back to a `DumbTerminal` when formatting is disabled or no terminal could be opened.
+pub fn stdout_with_choice(choice: ColorChoice) -> Box<StdoutTerminal> {
+    if choice.use_color(io::stdout().is_terminal()) {
+        stdout().unwrap_or_else(dumb_stdout)
+    } else {
+        dumb_stdout()
+    }
+}
+
+/// Return a Terminal wrapping stderr that formats its output according to `choice`.
+///
+/// Falls back to a `DumbTerminal` when formatting is disabled or no terminal could be opened.
+pub fn stderr_with_choice(choice: ColorChoice) -> Box<StderrTerminal> {
+    if choice.use_color(io::stderr().is_terminal()) {
+        stderr().unwrap_or_else(dumb_stderr)
+    } else {
+        dumb_stderr()
+    }
+}
+
 /// Terminal color definitions
 #[allow(missing_docs)]
 pub mod color {
@@ -138,6 +231,102 @@ pub mod color {
     pub const BRIGHT_MAGENTA: Color = 13;
     pub const BRIGHT_CYAN: Color = 14;
     pub const BRIGHT_WHITE: Color = 15;
+
+    /// Parses a `#rrggbb` or `#rgb` hex color into its red, green and blue components.
+    ///
+    /// Returns `None` if `s` is not in either form.
+    ///
+    /// ```
+    /// assert_eq!(term::color::parse_hex("#ff8000"), Some((0xff, 0x80, 0x00)));
+    /// assert_eq!(term::color::parse_hex("#f80"), Some((0xff, 0x88, 0x00)));
+    /// assert_eq!(term::color::parse_hex("ff8000"), None);
+    /// ```
+    pub fn parse_hex(s: &str) -> Option<(u8, u8, u8)> {
+        let hex = s.strip_prefix('#')?;
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize, len: usize| u8::from_str_radix(&hex[i * len..][..len], 16);
+        match hex.len() {
+            6 => Some((
+                channel(0, 2).ok()?,
+                channel(1, 2).ok()?,
+                channel(2, 2).ok()?,
+            )),
+            3 => Some((
+                channel(0, 1).ok()? * 0x11,
+                channel(1, 1).ok()? * 0x11,
+                channel(2, 1).ok()? * 0x11,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Converts an `anstyle` color to a terminal color.
+    ///
+    /// The 16 named colors and the 256-color palette map to the same indices; RGB colors map to
+    /// the nearest color in the 256-color palette.
+    #[cfg(feature = "anstyle")]
+    pub fn from_anstyle(color: anstyle::Color) -> Color {
+        match color {
+            anstyle::Color::Ansi(color) => Color::from(anstyle::Ansi256Color::from_ansi(color).0),
+            anstyle::Color::Ansi256(color) => Color::from(color.0),
+            anstyle::Color::Rgb(anstyle::RgbColor(r, g, b)) => nearest_256(r, g, b),
+        }
+    }
+
+    /// Converts a terminal color to an `anstyle` color.
+    ///
+    /// Colors 0 to 15 become named colors and the rest of the 256-color palette becomes indexed
+    /// colors. Returns `None` for colors past the 256-color palette.
+    #[cfg(feature = "anstyle")]
+    pub fn to_anstyle(color: Color) -> Option<anstyle::Color> {
+        let color = anstyle::Ansi256Color(u8::try_from(color).ok()?);
+        Some(match color.into_ansi() {
+            Some(ansi) => anstyle::Color::Ansi(ansi),
+            None => anstyle::Color::Ansi256(color),
+        })
+    }
+
+    /// Returns the entry of the 256-color palette's color cube or grayscale ramp nearest to the
+    /// given RGB value.
+    #[cfg(feature = "anstyle")]
+    fn nearest_256(r: u8, g: u8, b: u8) -> Color {
+        const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+        let distance = |(r1, g1, b1): (u8, u8, u8)| {
+            let d = |x: u8, y: u8| (i32::from(x) - i32::from(y)).pow(2);
+            d(r, r1) + d(g, g1) + d(b, b1)
+        };
+        let level = |c: u8| {
+            (0..LEVELS.len())
+                .min_by_key(|&i| (i32::from(LEVELS[i]) - i32::from(c)).abs())
+                .unwrap_or(0)
+        };
+        let (ri, gi, bi) = (level(r), level(g), level(b));
+        let cube = 16 + 36 * ri + 6 * gi + bi;
+        let cube_rgb = (LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+
+        let average = (u32::from(r) + u32::from(g) + u32::from(b)) / 3;
+        let gray = (average.saturating_sub(8) + 5) / 10;
+        let gray = gray.min(23);
+        let gray_level = (8 + gray * 10) as u8;
+
+        if distance((gray_level, gray_level, gray_level)) < distance(cube_rgb) {
+            232 + gray
+        } else {
+            cube as Color
+        }
+    }
+}
+
+/// How `Terminal::write_sanitized` treats control characters.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Sanitize {
+    /// Drop control characters.
+    Strip,
+    /// Replace control characters with a visible stand-in: the matching Unicode control picture
+    /// (e.g. `␛` for escape) where there is one, or `�` otherwise.
+    Visualize,
 }
 
 /// Terminal attributes for use with term.attr().
@@ -148,9 +337,13 @@ pub mod color {
 #[derive(Debug, PartialEq, Hash, Eq, Copy, Clone)]
 pub enum Attr {
     /// Bold (or possibly bright) mode
-    Bold,
+    ///
+    /// Turning bold off also turns off dim mode, as terminals have a single code for both.
+    Bold(bool),
     /// Dim mode, also called faint or half-bright. Often not supported
-    Dim,
+    ///
+    /// Turning dim off also turns off bold mode, as terminals have a single code for both.
+    Dim(bool),
     /// Italics mode. Often not supported
     Italic(bool),
     /// Underline mode
@@ -163,6 +356,11 @@ pub enum Attr {
     Reverse,
     /// Secure mode, also called invis mode. Hides the printed text
     Secure,
+    /// Protected mode. Protected text isn't erased by selective erase
+    ///
+    /// ANSI terminals have a code for turning protected mode off; terminfo can only do it with
+    /// `sgr`, which sets the other attributes again.
+    Protected(bool),
     /// Convenience attribute to set the foreground color
     ForegroundColor(color::Color),
     /// Convenience attribute to set the background color
@@ -192,6 +390,9 @@ pub enum Error {
     ///
     /// This is like `NotSupported`, but more specific.
     ColorOutOfRange,
+    /// Indicates that an argument contained a control character, which would have corrupted the
+    /// escape sequence it was to be embedded in.
+    InvalidControlCharacter,
 }
 
 // manually implemented because std::io::Error does not implement Eq/PartialEq
@@ -207,6 +408,7 @@ impl std::cmp::PartialEq for Error {
             TerminfoEntryNotFound => matches!(other, TerminfoEntryNotFound),
             CursorDestinationInvalid => matches!(other, CursorDestinationInvalid),
             ColorOutOfRange => matches!(other, ColorOutOfRange),
+            InvalidControlCharacter => matches!(other, InvalidControlCharacter),
         }
     }
 }
@@ -230,6 +432,7 @@ impl std::fmt::Display for Error {
             }
             CursorDestinationInvalid => f.write_str("could not move cursor to requested position"),
             ColorOutOfRange => f.write_str("color not supported by the terminal"),
+            InvalidControlCharacter => f.write_str("argument contains a control character"),
         }
     }
 }
@@ -275,6 +478,12 @@ impl std::convert::From<terminfo::parm::Error> for Error {
 
 /// A terminal with similar capabilities to an ANSI Terminal
 /// (foreground/background colors etc).
+///
+/// Text is written through `Write`, and formatting through the methods below. The trait can be
+/// implemented outside this crate, e.g. to wrap another `Terminal` or to render formatting
+/// differently: implement the required methods (returning `Err(Error::NotSupported)` for what
+/// the terminal can't do), and `Output` and the `get_ref`/`get_mut`/`into_inner` accessors for
+/// the underlying writer. `DumbTerminal` is a minimal example.
 pub trait Terminal: Write {
     /// The terminal's output writer type.
     type Output: Write;
@@ -297,14 +506,146 @@ pub trait Terminal: Write {
     /// was an error.
     fn bg(&mut self, color: color::Color) -> Result<()>;
 
+    /// Sets both the foreground and background colors.
+    ///
+    /// Bright colors are handled as in `fg` and `bg`. Both color changes are written to the
+    /// terminal at once, and nothing is written if either color is unsupported.
+    ///
+    /// Returns `Ok(())` if the color change codes were sent to the terminal, or `Err(e)` if there
+    /// was an error. The default implementation calls `fg` and then `bg`, so the foreground may
+    /// be changed even if the background color is unsupported.
+    fn set_colors(&mut self, fg: color::Color, bg: color::Color) -> Result<()> {
+        self.fg(fg)?;
+        self.bg(bg)
+    }
+
     /// Sets the given terminal attribute, if supported.  Returns `Ok(())` if the attribute is
     /// supported and was sent to the terminal, or `Err(e)` if there was an error or the attribute
     /// wasn't supported.
     fn attr(&mut self, attr: Attr) -> Result<()>;
 
+    /// Writes `text` with the given colors and attributes, then resets the terminal.
+    ///
+    /// Nothing besides `text` is written if no styling was requested.
+    ///
+    /// Returns `Ok(())` if the text and all styling codes were written, or `Err(e)` if there was
+    /// an error (including an unsupported color or attribute).
+    fn write_styled(
+        &mut self,
+        text: &str,
+        fg: Option<color::Color>,
+        bg: Option<color::Color>,
+        attrs: &[Attr],
+    ) -> Result<()> {
+        match (fg, bg) {
+            (Some(fg), Some(bg)) => self.set_colors(fg, bg)?,
+            (Some(fg), None) => self.fg(fg)?,
+            (None, Some(bg)) => self.bg(bg)?,
+            (None, None) => {}
+        }
+        for &attr in attrs {
+            self.attr(attr)?;
+        }
+        self.write_all(text.as_bytes())?;
+        if fg.is_some() || bg.is_some() || !attrs.is_empty() {
+            self.reset()?;
+        }
+        Ok(())
+    }
+
+    /// Writes a line made of differently styled parts, each with a foreground color (if any),
+    /// attributes and text, then resets the terminal once at the end.
+    ///
+    /// Only the changes from one part to the next are written: a part with the same style as the
+    /// previous one writes just its text, and one that adds a color or attributes writes just
+    /// those. Attributes can't reliably be turned off one at a time, so the terminal is reset
+    /// between parts when a part drops an attribute or the foreground color.
+    ///
+    /// Returns `Ok(())` if the text and all styling codes were written, or `Err(e)` if there was
+    /// an error (including an unsupported color or attribute).
+    fn styled_line(&mut self, parts: &[(Option<color::Color>, &[Attr], &str)]) -> Result<()> {
+        let mut fg = None;
+        let mut attrs: Vec<Attr> = Vec::new();
+        for &(part_fg, part_attrs, text) in parts {
+            if (fg.is_some() && part_fg.is_none()) || attrs.iter().any(|a| !part_attrs.contains(a))
+            {
+                self.reset()?;
+                fg = None;
+                attrs.clear();
+            }
+            if part_fg != fg {
+                if let Some(color) = part_fg {
+                    self.fg(color)?;
+                }
+                fg = part_fg;
+            }
+            for &attr in part_attrs {
+                if !attrs.contains(&attr) {
+                    self.attr(attr)?;
+                    attrs.push(attr);
+                }
+            }
+            self.write_all(text.as_bytes())?;
+        }
+        if fg.is_some() || !attrs.is_empty() {
+            self.reset()?;
+        }
+        Ok(())
+    }
+
+    /// Writes untrusted `text` without letting it control the terminal.
+    ///
+    /// All control characters except newlines and tabs are stripped or made visible, according to
+    /// `policy`, so that the text can't contain escape sequences that would change colors, move
+    /// the cursor or otherwise reconfigure the terminal.
+    fn write_sanitized(&mut self, text: &str, policy: Sanitize) -> io::Result<()> {
+        let mut buf = String::with_capacity(text.len());
+        for c in text.chars() {
+            if !c.is_control() || c == '\n' || c == '\t' {
+                buf.push(c);
+            } else if policy == Sanitize::Visualize {
+                buf.push(match c {
+                    '\0'..='\x1F' => char::from_u32(0x2400 + c as u32).unwrap(),
+                    '\x7F' => '\u{2421}',
+                    _ => char::REPLACEMENT_CHARACTER,
+                });
+            }
+        }
+        self.write_all(buf.as_bytes())
+    }
+
+    /// Writes `bytes` to the terminal as they are.
+    ///
+    /// The bytes needn't be UTF-8: styling only emits escape sequences and never inspects the
+    /// text written between them, so byte-oriented output (e.g. Latin-1 text or a hex dump) can
+    /// be freely mixed with colors and attributes. Unlike `write_sanitized`, nothing is filtered.
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write_all(bytes)
+    }
+
     /// Returns whether the given terminal attribute is supported.
     fn supports_attr(&self, attr: Attr) -> bool;
 
+    /// Returns whether bold text is supported.
+    fn supports_bold(&self) -> bool {
+        self.supports_attr(Attr::Bold(true))
+    }
+
+    /// Returns whether dim text is supported.
+    fn supports_dim(&self) -> bool {
+        self.supports_attr(Attr::Dim(true))
+    }
+
+    /// Returns whether italic text is supported.
+    fn supports_italic(&self) -> bool {
+        self.supports_attr(Attr::Italic(true))
+    }
+
+    /// Returns whether underlined text is supported.
+    fn supports_underline(&self) -> bool {
+        self.supports_attr(Attr::Underline(true))
+    }
+
     /// Resets all terminal attributes and colors to their defaults.
     ///
     /// Returns `Ok(())` if the reset code was printed, or `Err(e)` if there was an error.
@@ -325,6 +666,23 @@ pub trait Terminal: Write {
     /// return `Err(Error::NotSupported)`.
     fn supports_color(&self) -> bool;
 
+    /// Returns true if `fg` and `bg` would emit the given color (possibly dimmed to its normal
+    /// counterpart, see `fg`) rather than fail with `Error::ColorOutOfRange` or
+    /// `Error::NotSupported`.
+    ///
+    /// The default implementation returns `supports_color()`.
+    fn supports_color_index(&self, _color: color::Color) -> bool {
+        self.supports_color()
+    }
+
+    /// Returns true if the terminal supports 24-bit ("true") color.
+    ///
+    /// Use this to pick between RGB colors and a palette. The default implementation returns
+    /// `false`.
+    fn truecolor(&self) -> bool {
+        false
+    }
+
     /// Moves the cursor up one line.
     ///
     /// Returns `Ok(())` if the cursor movement code was printed, or `Err(e)` if there was an
@@ -333,14 +691,56 @@ pub trait Terminal: Write {
 
     /// Deletes the text from the cursor location to the end of the line.
     ///
+    /// Whether the erased text is replaced with the current or the default background color
+    /// depends on the terminal (see `TerminfoTerminal::back_color_erase`).
+    ///
     /// Returns `Ok(())` if the deletion code was printed, or `Err(e)` if there was an error.
     fn delete_line(&mut self) -> Result<()>;
 
     /// Moves the cursor to the left edge of the current line.
     ///
-    /// Returns `Ok(true)` if the deletion code was printed, or `Err(e)` if there was an error.
+    /// Returns `Ok(())` if the carriage return was printed, or `Err(e)` if there was an error.
     fn carriage_return(&mut self) -> Result<()>;
 
+    /// Begins a synchronized update.
+    ///
+    /// Output until the matching `end_sync_update` is buffered by the terminal and displayed at
+    /// once, which avoids showing half-drawn frames. Terminals that don't implement synchronized
+    /// updates ignore this.
+    ///
+    /// Returns `Ok(())` if the code was printed, or `Err(e)` if there was an error. The default
+    /// implementation returns `Err(Error::NotSupported)`.
+    fn begin_sync_update(&mut self) -> Result<()> {
+        Err(Error::NotSupported)
+    }
+
+    /// Ends a synchronized update started with `begin_sync_update`.
+    ///
+    /// Returns `Ok(())` if the code was printed, or `Err(e)` if there was an error. The default
+    /// implementation returns `Err(Error::NotSupported)`.
+    fn end_sync_update(&mut self) -> Result<()> {
+        Err(Error::NotSupported)
+    }
+
+    /// Writes `text` as a hyperlink to `url` (using OSC 8).
+    ///
+    /// Returns `Err(Error::NotSupported)` without writing anything if the terminal can't display
+    /// hyperlinks, in which case the caller may want to write `text` (and possibly `url`) itself.
+    /// Returns `Err(Error::InvalidControlCharacter)` if `url` contains control characters. The
+    /// default implementation returns `Err(Error::NotSupported)`.
+    fn hyperlink(&mut self, _url: &str, _text: &str) -> Result<()> {
+        Err(Error::NotSupported)
+    }
+
+    /// Returns the primary name of the terminal description in use, if any.
+    ///
+    /// For terminfo-backed terminals this is the first name of the loaded entry, which may differ
+    /// from `$TERM` when an alias or the built-in fallback was used. The default implementation
+    /// returns `None`.
+    fn term_name(&self) -> Option<&str> {
+        None
+    }
+
     /// Gets an immutable reference to the stream inside
     fn get_ref(&self) -> &Self::Output;
 
@@ -352,3 +752,20 @@ pub trait Terminal: Write {
     where
         Self: Sized;
 }
+
+/// Summarizes a boxed terminal (such as a `Box<StdoutTerminal>`) by its name and color support,
+/// so that structs holding one can derive `Debug`.
+impl<O: Write> std::fmt::Debug for dyn Terminal<Output = O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Terminal")
+            .field("name", &self.term_name())
+            .field("supports_color", &self.supports_color())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<O: Write> std::fmt::Debug for dyn Terminal<Output = O> + Send {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        (self as &dyn Terminal<Output = O>).fmt(f)
+    }
+}
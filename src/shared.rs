@@ -0,0 +1,114 @@
+//! A writer shared between threads
+
+use std::io;
+use std::io::prelude::*;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A cloneable handle to a writer behind an `Arc<Mutex<_>>`, for building terminals that write
+/// to the same place from several threads, e.g. `TerminfoTerminal<SharedWriter<Stdout>>`.
+///
+/// Each `write` and `flush` call locks the writer for its duration only, so output from
+/// different handles can interleave between calls. To write a whole update at once, buffer it
+/// (see `TerminfoTerminal::set_buffer_capacity`) or hold `lock` while writing to the writer
+/// directly. A writer whose lock was poisoned by a panic is still written to.
+///
+/// ```
+/// use std::io::Write;
+/// use term::{SharedWriter, TerminfoTerminal};
+///
+/// let out = SharedWriter::new(Vec::new());
+/// let mut t = TerminfoTerminal::ansi(out.clone());
+/// let handle = std::thread::spawn(move || write!(t, "hi").unwrap());
+/// handle.join().unwrap();
+/// assert_eq!(*out.lock(), b"hi");
+/// ```
+#[derive(Debug, Default)]
+pub struct SharedWriter<W> {
+    inner: Arc<Mutex<W>>,
+}
+
+impl<W> SharedWriter<W> {
+    /// Create a new SharedWriter owning `writer`.
+    pub fn new(writer: W) -> SharedWriter<W> {
+        SharedWriter {
+            inner: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Locks the writer, blocking until no other handle is writing to it.
+    pub fn lock(&self) -> MutexGuard<'_, W> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Gets a reference to the shared writer.
+    pub fn get_ref(&self) -> &Arc<Mutex<W>> {
+        &self.inner
+    }
+
+    /// Returns the shared writer, destroying this handle.
+    pub fn into_inner(self) -> Arc<Mutex<W>> {
+        self.inner
+    }
+}
+
+// Not derived, as that would require `W: Clone`.
+impl<W> Clone for SharedWriter<W> {
+    fn clone(&self) -> SharedWriter<W> {
+        SharedWriter {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<W> From<Arc<Mutex<W>>> for SharedWriter<W> {
+    fn from(inner: Arc<Mutex<W>>) -> SharedWriter<W> {
+        SharedWriter { inner }
+    }
+}
+
+impl<W: Write> Write for SharedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.lock().write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedWriter;
+    use crate::{color, Terminal, TerminfoTerminal};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_shared_writer() {
+        let shared = Arc::new(Mutex::new(Vec::new()));
+        let out = SharedWriter::from(Arc::clone(&shared));
+        let mut first = TerminfoTerminal::ansi(out.clone());
+        let mut second = TerminfoTerminal::ansi(out);
+        first.fg(color::RED).unwrap();
+        write!(first, "a").unwrap();
+        second.write_all(b"b").unwrap();
+        first.reset().unwrap();
+        assert_eq!(*shared.lock().unwrap(), b"\x1B[31mab\x1B[0m");
+
+        // A panic while holding the lock doesn't stop further output.
+        let poisoner = second.get_ref().clone();
+        std::thread::spawn(move || {
+            let _guard = poisoner.lock();
+            panic!("poison");
+        })
+        .join()
+        .unwrap_err();
+        write!(second, "c").unwrap();
+        assert!(shared.is_poisoned());
+        assert!(second.get_ref().lock().ends_with(b"c"));
+    }
+}
@@ -0,0 +1,280 @@
+//! A builder for combinations of colors and attributes.
+
+use crate::color;
+use crate::Attr;
+use crate::Result;
+use crate::Terminal;
+
+/// A combination of colors and attributes, built up by chaining methods.
+///
+/// ```no_run
+/// use term::Style;
+///
+/// let mut t = term::stdout().unwrap();
+/// let error = Style::new().red().bold();
+/// error.paint("error").write_to(&mut *t).unwrap();
+/// writeln!(t, ": something went wrong").unwrap();
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
+pub struct Style {
+    fg: Option<color::Color>,
+    bg: Option<color::Color>,
+    attrs: Vec<Attr>,
+}
+
+macro_rules! colors {
+    ($($fg:ident, $bg:ident => $color:ident;)*) => {
+        $(
+            #[doc = concat!("Sets the foreground color to `color::", stringify!($color), "`.")]
+            pub fn $fg(self) -> Style {
+                self.fg(color::$color)
+            }
+
+            #[doc = concat!("Sets the background color to `color::", stringify!($color), "`.")]
+            pub fn $bg(self) -> Style {
+                self.bg(color::$color)
+            }
+        )*
+    };
+}
+
+impl Style {
+    /// Create a style with no colors or attributes.
+    pub fn new() -> Style {
+        Style::default()
+    }
+
+    /// Sets the foreground color.
+    pub fn fg(mut self, color: color::Color) -> Style {
+        self.fg = Some(color);
+        self
+    }
+
+    /// Sets the background color.
+    pub fn bg(mut self, color: color::Color) -> Style {
+        self.bg = Some(color);
+        self
+    }
+
+    /// Adds an attribute.
+    ///
+    /// `Attr::ForegroundColor` and `Attr::BackgroundColor` set the colors, as with `fg` and `bg`.
+    pub fn attr(mut self, attr: Attr) -> Style {
+        match attr {
+            Attr::ForegroundColor(color) => self.fg(color),
+            Attr::BackgroundColor(color) => self.bg(color),
+            attr => {
+                if !self.attrs.contains(&attr) {
+                    self.attrs.push(attr);
+                }
+                self
+            }
+        }
+    }
+
+    colors! {
+        black, on_black => BLACK;
+        red, on_red => RED;
+        green, on_green => GREEN;
+        yellow, on_yellow => YELLOW;
+        blue, on_blue => BLUE;
+        magenta, on_magenta => MAGENTA;
+        cyan, on_cyan => CYAN;
+        white, on_white => WHITE;
+    }
+
+    /// Adds bold.
+    pub fn bold(self) -> Style {
+        self.attr(Attr::Bold(true))
+    }
+
+    /// Adds dim.
+    pub fn dim(self) -> Style {
+        self.attr(Attr::Dim(true))
+    }
+
+    /// Adds italics.
+    pub fn italic(self) -> Style {
+        self.attr(Attr::Italic(true))
+    }
+
+    /// Adds underline.
+    pub fn underline(self) -> Style {
+        self.attr(Attr::Underline(true))
+    }
+
+    /// Adds blink.
+    pub fn blink(self) -> Style {
+        self.attr(Attr::Blink)
+    }
+
+    /// Adds reverse video.
+    pub fn reverse(self) -> Style {
+        self.attr(Attr::Reverse)
+    }
+
+    /// Returns true if the style has no colors or attributes.
+    pub fn is_plain(&self) -> bool {
+        self.fg.is_none() && self.bg.is_none() && self.attrs.is_empty()
+    }
+
+    /// Pairs `text` with this style, ready to be written to a terminal.
+    pub fn paint<'a>(&self, text: &'a str) -> StyledStr<'a> {
+        StyledStr {
+            style: self.clone(),
+            text,
+        }
+    }
+}
+
+/// Text with a `Style`, created by `Style::paint`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct StyledStr<'a> {
+    style: Style,
+    text: &'a str,
+}
+
+impl StyledStr<'_> {
+    /// Returns the style.
+    pub fn style(&self) -> &Style {
+        &self.style
+    }
+
+    /// Returns the text.
+    pub fn text(&self) -> &str {
+        self.text
+    }
+
+    /// Writes the text to `term` in this style, then resets the terminal.
+    ///
+    /// This is `Terminal::write_styled`, so only the codes the style needs are written, and
+    /// nothing but the text for a plain style.
+    pub fn write_to<T: Terminal + ?Sized>(&self, term: &mut T) -> Result<()> {
+        term.write_styled(self.text, self.style.fg, self.style.bg, &self.style.attrs)
+    }
+}
+
+/// Converts an `anstyle` style, mapping colors with `color::from_anstyle`.
+///
+/// Effects without a matching `Attr` (strikethrough) and the underline color are dropped, and all
+/// underline styles become a plain underline.
+#[cfg(feature = "anstyle")]
+impl From<anstyle::Style> for Style {
+    fn from(style: anstyle::Style) -> Style {
+        use anstyle::Effects;
+
+        const EFFECTS: &[(Effects, Attr)] = &[
+            (Effects::BOLD, Attr::Bold(true)),
+            (Effects::DIMMED, Attr::Dim(true)),
+            (Effects::ITALIC, Attr::Italic(true)),
+            (Effects::UNDERLINE, Attr::Underline(true)),
+            (Effects::DOUBLE_UNDERLINE, Attr::Underline(true)),
+            (Effects::CURLY_UNDERLINE, Attr::Underline(true)),
+            (Effects::DOTTED_UNDERLINE, Attr::Underline(true)),
+            (Effects::DASHED_UNDERLINE, Attr::Underline(true)),
+            (Effects::BLINK, Attr::Blink),
+            (Effects::INVERT, Attr::Reverse),
+            (Effects::HIDDEN, Attr::Secure),
+        ];
+
+        let effects = style.get_effects();
+        let mut result = Style {
+            fg: style.get_fg_color().map(color::from_anstyle),
+            bg: style.get_bg_color().map(color::from_anstyle),
+            attrs: Vec::new(),
+        };
+        for &(effect, attr) in EFFECTS {
+            if effects.contains(effect) {
+                result = result.attr(attr);
+            }
+        }
+        result
+    }
+}
+
+/// Converts to an `anstyle` style, mapping colors with `color::to_anstyle`.
+///
+/// Standout becomes inverted text. Colors past the 256-color palette and attributes `anstyle` has
+/// no effect for are dropped.
+#[cfg(feature = "anstyle")]
+impl From<Style> for anstyle::Style {
+    fn from(style: Style) -> anstyle::Style {
+        use anstyle::Effects;
+
+        let mut effects = Effects::new();
+        for attr in &style.attrs {
+            effects |= match *attr {
+                Attr::Bold(true) => Effects::BOLD,
+                Attr::Dim(true) => Effects::DIMMED,
+                Attr::Italic(true) => Effects::ITALIC,
+                Attr::Underline(true) => Effects::UNDERLINE,
+                Attr::Blink => Effects::BLINK,
+                Attr::Standout(true) | Attr::Reverse => Effects::INVERT,
+                Attr::Secure => Effects::HIDDEN,
+                _ => Effects::new(),
+            };
+        }
+        anstyle::Style::new()
+            .fg_color(style.fg.and_then(color::to_anstyle))
+            .bg_color(style.bg.and_then(color::to_anstyle))
+            .effects(effects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Style;
+    use crate::test::TestTerminal;
+    use crate::{color, Attr};
+
+    #[test]
+    fn test_style() {
+        let mut t = TestTerminal::new();
+        let style = Style::new().red().on_blue().bold().bold().underline();
+        style.paint("hi").write_to(&mut t).unwrap();
+        assert_eq!(t.written(), b"\x1B[31m\x1B[44m\x1B[1m\x1B[4mhi\x1B[m");
+
+        t.clear();
+        Style::new().paint("plain").write_to(&mut t).unwrap();
+        assert_eq!(t.written(), b"plain");
+
+        assert_eq!(
+            Style::new().attr(Attr::ForegroundColor(color::GREEN)),
+            Style::new().green()
+        );
+        assert!(Style::new().is_plain());
+        assert!(!Style::new().italic().is_plain());
+    }
+
+    #[cfg(feature = "anstyle")]
+    #[test]
+    fn test_anstyle() {
+        use anstyle::{Ansi256Color, AnsiColor, Effects, RgbColor};
+
+        assert_eq!(
+            color::from_anstyle(AnsiColor::BrightRed.into()),
+            color::BRIGHT_RED
+        );
+        assert_eq!(color::from_anstyle(Ansi256Color(200).into()), 200);
+        assert_eq!(color::from_anstyle(RgbColor(255, 0, 0).into()), 196);
+        assert_eq!(color::from_anstyle(RgbColor(128, 128, 128).into()), 244);
+        assert_eq!(color::to_anstyle(color::RED), Some(AnsiColor::Red.into()));
+        assert_eq!(color::to_anstyle(100), Some(Ansi256Color(100).into()));
+        assert_eq!(color::to_anstyle(256), None);
+
+        let style = anstyle::Style::new()
+            .fg_color(Some(AnsiColor::Red.into()))
+            .bg_color(Some(AnsiColor::Blue.into()))
+            .effects(Effects::BOLD | Effects::CURLY_UNDERLINE | Effects::STRIKETHROUGH);
+        let converted = Style::from(style);
+        assert_eq!(converted, Style::new().red().on_blue().bold().underline());
+        assert_eq!(
+            anstyle::Style::from(converted),
+            style.effects(Effects::BOLD | Effects::UNDERLINE)
+        );
+        assert_eq!(
+            anstyle::Style::from(Style::new().attr(Attr::Standout(true))),
+            anstyle::Style::new().invert()
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! A terminal that duplicates its output to two terminals
+
+use std::io;
+use std::io::prelude::*;
+
+use crate::color;
+use crate::Attr;
+use crate::Result;
+use crate::Terminal;
+
+/// A Terminal that forwards text and formatting to two terminals, e.g. to show colored output
+/// live while also capturing it.
+///
+/// Every call is made on both terminals, even if the first fails; the first error is returned.
+/// As such, an error doesn't mean nothing was written. The `supports_*` methods return true only
+/// if both terminals support the feature. `get_ref`, `get_mut`, `into_inner` and `term_name`
+/// refer to the first terminal; use `second` or `into_parts` to get at the other one.
+///
+/// ```
+/// use term::{DumbTerminal, TeeTerminal, Terminal};
+///
+/// let live = term::TerminfoTerminal::ansi(Vec::new());
+/// let log = DumbTerminal::new(Vec::new());
+/// let mut t = TeeTerminal::new(live, log);
+/// t.write_styled("ok", Some(term::color::GREEN), None, &[]).ok();
+/// let (live, log) = t.into_parts();
+/// assert_eq!(live.get_ref(), b"\x1B[32mok\x1B[0m");
+/// assert_eq!(log.get_ref(), b"ok");
+/// ```
+#[derive(Clone, Debug)]
+pub struct TeeTerminal<A, B> {
+    first: A,
+    second: B,
+}
+
+/// Combines the results of calling a method on both terminals.
+fn both(first: Result<()>, second: Result<()>) -> Result<()> {
+    first.and(second)
+}
+
+impl<A: Terminal, B: Terminal> TeeTerminal<A, B> {
+    /// Create a new TeeTerminal writing to `first` and `second`.
+    pub fn new(first: A, second: B) -> TeeTerminal<A, B> {
+        TeeTerminal { first, second }
+    }
+
+    /// Gets a reference to the first terminal.
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// Gets a reference to the second terminal.
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+
+    /// Returns both terminals, destroying the `TeeTerminal`.
+    pub fn into_parts(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<A: Terminal, B: Terminal> Terminal for TeeTerminal<A, B> {
+    type Output = A::Output;
+
+    fn fg(&mut self, color: color::Color) -> Result<()> {
+        both(self.first.fg(color), self.second.fg(color))
+    }
+
+    fn bg(&mut self, color: color::Color) -> Result<()> {
+        both(self.first.bg(color), self.second.bg(color))
+    }
+
+    fn set_colors(&mut self, fg: color::Color, bg: color::Color) -> Result<()> {
+        both(
+            self.first.set_colors(fg, bg),
+            self.second.set_colors(fg, bg),
+        )
+    }
+
+    fn attr(&mut self, attr: Attr) -> Result<()> {
+        both(self.first.attr(attr), self.second.attr(attr))
+    }
+
+    fn supports_attr(&self, attr: Attr) -> bool {
+        self.first.supports_attr(attr) && self.second.supports_attr(attr)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        both(self.first.reset(), self.second.reset())
+    }
+
+    fn supports_reset(&self) -> bool {
+        self.first.supports_reset() && self.second.supports_reset()
+    }
+
+    fn supports_color(&self) -> bool {
+        self.first.supports_color() && self.second.supports_color()
+    }
+
+    fn supports_color_index(&self, color: color::Color) -> bool {
+        self.first.supports_color_index(color) && self.second.supports_color_index(color)
+    }
+
+    fn truecolor(&self) -> bool {
+        self.first.truecolor() && self.second.truecolor()
+    }
+
+    fn cursor_up(&mut self) -> Result<()> {
+        both(self.first.cursor_up(), self.second.cursor_up())
+    }
+
+    fn delete_line(&mut self) -> Result<()> {
+        both(self.first.delete_line(), self.second.delete_line())
+    }
+
+    fn carriage_return(&mut self) -> Result<()> {
+        both(self.first.carriage_return(), self.second.carriage_return())
+    }
+
+    fn begin_sync_update(&mut self) -> Result<()> {
+        both(
+            self.first.begin_sync_update(),
+            self.second.begin_sync_update(),
+        )
+    }
+
+    fn end_sync_update(&mut self) -> Result<()> {
+        both(self.first.end_sync_update(), self.second.end_sync_update())
+    }
+
+    /// Writes a hyperlink to both terminals.
+    ///
+    /// A terminal that can't display hyperlinks gets the plain `text` instead, so that both show
+    /// the same text. `Err(Error::NotSupported)` is only returned (without writing anything) if
+    /// neither terminal can display hyperlinks.
+    fn hyperlink(&mut self, url: &str, text: &str) -> Result<()> {
+        let first = self.first.hyperlink(url, text);
+        let second = self.second.hyperlink(url, text);
+        match (first, second) {
+            (Err(crate::Error::NotSupported), Err(crate::Error::NotSupported)) => {
+                Err(crate::Error::NotSupported)
+            }
+            (Err(crate::Error::NotSupported), second) => {
+                self.first.write_all(text.as_bytes())?;
+                second
+            }
+            (first, Err(crate::Error::NotSupported)) => {
+                self.second.write_all(text.as_bytes())?;
+                first
+            }
+            (first, second) => both(first, second),
+        }
+    }
+
+    fn term_name(&self) -> Option<&str> {
+        self.first.term_name()
+    }
+
+    fn get_ref(&self) -> &A::Output {
+        self.first.get_ref()
+    }
+
+    fn get_mut(&mut self) -> &mut A::Output {
+        self.first.get_mut()
+    }
+
+    fn into_inner(self) -> A::Output
+    where
+        Self: Sized,
+    {
+        self.first.into_inner()
+    }
+}
+
+impl<A: Terminal, B: Terminal> Write for TeeTerminal<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Both terminals must get all of `buf`, or they'd disagree on what to write next.
+        let first = self.first.write_all(buf);
+        let second = self.second.write_all(buf);
+        first.and(second).map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let first = self.first.flush();
+        let second = self.second.flush();
+        first.and(second)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TeeTerminal;
+    use crate::test::TestTerminal;
+    use crate::{color, DumbTerminal, Terminal};
+
+    #[test]
+    fn test_tee() {
+        use std::io::Write;
+
+        let mut t = TeeTerminal::new(TestTerminal::new(), DumbTerminal::new(Vec::new()));
+        t.fg(color::RED).unwrap();
+        write!(t, "hi").unwrap();
+        t.reset().unwrap();
+        assert_eq!(t.first().written(), b"\x1B[31mhi\x1B[m");
+        assert_eq!(t.second().get_ref(), b"hi");
+        assert!(!t.supports_color());
+        assert_eq!(t.cursor_up(), Err(crate::Error::NotSupported));
+        assert_eq!(t.first().written(), b"\x1B[31mhi\x1B[m\x1B[A");
+
+        let mut t = TeeTerminal::new(TestTerminal::new(), DumbTerminal::new(Vec::new()));
+        t.hyperlink("http://a", "link").unwrap();
+        assert_eq!(t.second().get_ref(), b"link");
+    }
+}
@@ -12,20 +12,25 @@
 
 use std::collections::HashMap;
 use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::{BufReader, BufWriter};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::thread;
 
 #[cfg(windows)]
 use crate::win;
 
-use self::parm::{expand, Param, Variables};
+use self::parm::{expand, expand_with_padding, Param, Variables};
 use self::parser::compiled::parse;
-use self::searcher::get_dbpath_for_term;
+use self::searcher::get_dbpath_for_term_in;
 use self::Error::*;
 use crate::color;
+use crate::width::ColumnTracker;
 use crate::Attr;
 use crate::Result;
 use crate::Terminal;
@@ -44,7 +49,43 @@ fn is_ansi(name: &str) -> bool {
     }
 }
 
+/// `op` for ANSI terminals.
+const ANSI_OP: &[u8] = b"\x1B[39;49m";
+/// `setaf` for ANSI terminals, using the aixterm codes for the bright colors and the xterm
+/// 256-color codes beyond them.
+pub(crate) const ANSI_SETAF: &[u8] =
+    b"\x1B[%?%p1%{8}%<%t3%p1%d%e%p1%{16}%<%t9%p1%{8}%-%d%e38;5;%p1%d%;m";
+/// `setab` for ANSI terminals, see `ANSI_SETAF`.
+pub(crate) const ANSI_SETAB: &[u8] =
+    b"\x1B[%?%p1%{8}%<%t4%p1%d%e%p1%{16}%<%t10%p1%{8}%-%d%e48;5;%p1%d%;m";
+
+/// The entry `TerminfoTerminal::new` last loaded, along with the environment it was found in.
+static CACHE: Mutex<Option<(Environment, Arc<TermInfo>)>> = Mutex::new(None);
+
+/// Returns the entry for `environment`, only loading it again if the variables that locate it
+/// changed since it was last loaded.
+fn cached_from_environment(environment: &Environment) -> Result<Arc<TermInfo>> {
+    let locator = environment.locator();
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((cached, ti)) = &*cache {
+        if *cached == locator {
+            return Ok(ti.clone());
+        }
+    }
+    let ti = Arc::new(TermInfo::from_environment(&locator)?);
+    *cache = Some((locator, ti.clone()));
+    Ok(ti)
+}
+
+/// Forgets the entry cached by `TerminfoTerminal::new`, see `crate::clear_terminfo_cache`.
+pub(crate) fn clear_cache() {
+    *CACHE.lock().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
 /// A parsed terminfo database entry.
+///
+/// `TermInfo` is `Send` and `Sync`; parse it once and share it between terminals (and threads)
+/// through an `Arc`, see `TerminfoTerminal::new_with_terminfo`.
 #[derive(Debug, Clone)]
 pub struct TermInfo {
     /// Names for the terminal
@@ -57,12 +98,93 @@ pub struct TermInfo {
     pub strings: HashMap<&'static str, Vec<u8>>,
 }
 
+/// The environment variables used to find the terminal's description, along with those a
+/// `TerminfoTerminal` consults for what the description doesn't say.
+///
+/// `TermInfo::from_env` reads these from the process environment; build one by hand and pass
+/// it to `TermInfo::from_environment` to look up a terminal independently of it, or to
+/// `TerminfoTerminal::set_environment`.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Environment {
+    /// `TERM`, the terminal's name
+    pub term: Option<String>,
+    /// `MSYSCON`, which identifies mintty when `TERM` is unset
+    pub msyscon: Option<String>,
+    /// `TERMINFO`, the database (or entry) to search first
+    pub terminfo: Option<PathBuf>,
+    /// `TERMINFO_DIRS`, a colon-separated list of databases to search
+    pub terminfo_dirs: Option<String>,
+    /// The home directory, whose `.terminfo` database is searched
+    pub home: Option<PathBuf>,
+    /// `COLORTERM`, which can claim more colors than the entry declares, see
+    /// `TerminfoTerminal::new`
+    pub colorterm: Option<String>,
+    /// `COLUMNS`, the width of the terminal, see `TerminfoTerminal::dims`
+    pub columns: Option<String>,
+    /// `LINES`, the height of the terminal, see `TerminfoTerminal::dims`
+    pub lines: Option<String>,
+    /// `TMUX`, set by tmux for the programs it runs, see `TerminfoTerminal::multiplexer`
+    pub tmux: Option<String>,
+    /// `STY`, set by screen for the programs it runs, see `TerminfoTerminal::multiplexer`
+    pub sty: Option<String>,
+    /// `LC_ALL`, which overrides the other locale variables, see
+    /// `TerminfoTerminal::prefers_unicode_lines`
+    pub lc_all: Option<String>,
+    /// `LC_CTYPE`, the locale's character encoding
+    pub lc_ctype: Option<String>,
+    /// `LANG`, the default locale
+    pub lang: Option<String>,
+    /// `TERM_PROGRAM`, the terminal emulator, see `TerminfoTerminal::term_program`
+    pub term_program: Option<String>,
+    /// `TERM_PROGRAM_VERSION`, the version of the terminal emulator
+    pub term_program_version: Option<String>,
+}
+
+impl Environment {
+    /// Reads the environment of the current process.
+    pub fn from_process() -> Environment {
+        Environment {
+            term: env::var("TERM").ok(),
+            msyscon: env::var("MSYSCON").ok(),
+            terminfo: env::var_os("TERMINFO").map(PathBuf::from),
+            terminfo_dirs: env::var("TERMINFO_DIRS").ok(),
+            home: home::home_dir(),
+            colorterm: env::var("COLORTERM").ok(),
+            columns: env::var("COLUMNS").ok(),
+            lines: env::var("LINES").ok(),
+            tmux: env::var("TMUX").ok(),
+            sty: env::var("STY").ok(),
+            lc_all: env::var("LC_ALL").ok(),
+            lc_ctype: env::var("LC_CTYPE").ok(),
+            lang: env::var("LANG").ok(),
+            term_program: env::var("TERM_PROGRAM").ok(),
+            term_program_version: env::var("TERM_PROGRAM_VERSION").ok(),
+        }
+    }
+
+    /// Returns just the variables used to find the terminal's description.
+    fn locator(&self) -> Environment {
+        Environment {
+            term: self.term.clone(),
+            msyscon: self.msyscon.clone(),
+            terminfo: self.terminfo.clone(),
+            terminfo_dirs: self.terminfo_dirs.clone(),
+            home: self.home.clone(),
+            ..Environment::default()
+        }
+    }
+}
+
 impl TermInfo {
     /// Create a `TermInfo` based on current environment.
     pub fn from_env() -> Result<TermInfo> {
-        let term_var = env::var("TERM").ok();
-        let term_name = term_var.as_deref().or_else(|| {
-            env::var("MSYSCON").ok().and_then(|s| {
+        TermInfo::from_environment(&Environment::from_process())
+    }
+
+    /// Create a `TermInfo` based on the given environment, rather than that of the process.
+    pub fn from_environment(environment: &Environment) -> Result<TermInfo> {
+        let term_name = environment.term.as_deref().or_else(|| {
+            environment.msyscon.as_deref().and_then(|s| {
                 if s == "mintty.exe" {
                     Some("msyscon")
                 } else {
@@ -77,20 +199,33 @@ impl TermInfo {
                 // Microsoft people seem to be fine with pretending to be xterm:
                 // https://github.com/Microsoft/WSL/issues/1446
                 // The basic ANSI fallback terminal will be uses.
-                return TermInfo::from_name("xterm");
+                return TermInfo::from_name_in("xterm", environment);
             }
         }
 
+        if term_name == Some("dumb") {
+            // Don't depend on the database for this one; minimal containers often set TERM=dumb
+            // without shipping any terminfo entries.
+            return Ok(TermInfo::dumb());
+        }
+
         if let Some(term_name) = term_name {
-            TermInfo::from_name(term_name)
+            TermInfo::from_name_in(term_name, environment)
         } else {
             Err(crate::Error::TermUnset)
         }
     }
 
     /// Create a `TermInfo` for the named terminal.
+    ///
+    /// If the database has no entry for an ANSI-like terminal, this falls back to `TermInfo::ansi`
+    /// (limited to 8 colors unless the name contains `256color`).
     pub fn from_name(name: &str) -> Result<TermInfo> {
-        if let Some(path) = get_dbpath_for_term(name) {
+        TermInfo::from_name_in(name, &Environment::from_process())
+    }
+
+    fn from_name_in(name: &str, environment: &Environment) -> Result<TermInfo> {
+        if let Some(path) = get_dbpath_for_term_in(name, environment) {
             match TermInfo::from_path(path) {
                 Ok(term) => return Ok(term),
                 // Skip IO Errors (e.g., permission denied).
@@ -101,27 +236,133 @@ impl TermInfo {
         }
         // Basic ANSI fallback terminal.
         if is_ansi(name) {
-            let mut strings = HashMap::new();
-            strings.insert("sgr0", b"\x1B[0m".to_vec());
-            strings.insert("bold", b"\x1B[1m".to_vec());
-            strings.insert("setaf", b"\x1B[3%p1%dm".to_vec());
-            strings.insert("setab", b"\x1B[4%p1%dm".to_vec());
-
-            let mut numbers = HashMap::new();
-            numbers.insert("colors", 8);
-
-            Ok(TermInfo {
-                names: vec![name.to_owned()],
-                bools: HashMap::new(),
-                numbers,
-                strings,
-            })
+            Ok(TermInfo::ansi_fallback(name))
         } else {
             Err(crate::Error::TerminfoEntryNotFound)
         }
     }
 
+    /// The built-in ANSI description, renamed to `name`.
+    fn ansi_fallback(name: &str) -> TermInfo {
+        let mut ti = TermInfo::ansi();
+        ti.names = vec![name.to_owned()];
+        // Only assume 256 colors when the name asks for them.
+        if !name.contains("256color") {
+            ti.numbers.insert("colors", 8);
+            ti.numbers.insert("pairs", 64);
+        }
+        ti
+    }
+
+    /// The built-in description of mintty, the MSYS2 and Cygwin console.
+    ///
+    /// This is what `from_env` uses when `TERM` is unset but `MSYSCON` is `mintty.exe`, and the
+    /// database has no `msyscon` entry: the 8-color version of `TermInfo::ansi`, named
+    /// `msyscon`.
+    pub fn msys() -> TermInfo {
+        TermInfo::ansi_fallback("msyscon")
+    }
+
+    /// The built-in description of a dumb terminal, matching ncurses' `dumb` entry.
+    ///
+    /// A dumb terminal has no colors, attributes or cursor movement. `from_env` uses this when
+    /// `TERM` is `dumb`.
+    pub fn dumb() -> TermInfo {
+        let mut bools = HashMap::new();
+        bools.insert("am", true);
+
+        let mut numbers = HashMap::new();
+        numbers.insert("cols", 80);
+
+        let mut strings = HashMap::new();
+        strings.insert("bel", b"\x07".to_vec());
+        strings.insert("cr", b"\r".to_vec());
+        strings.insert("cud1", b"\n".to_vec());
+        strings.insert("ind", b"\n".to_vec());
+
+        TermInfo {
+            names: vec!["dumb".to_owned(), "80-column dumb tty".to_owned()],
+            bools,
+            numbers,
+            strings,
+        }
+    }
+
+    /// The built-in description of a generic 256-color ANSI terminal, modeled on `xterm-256color`.
+    ///
+    /// It covers colors, the common attributes, cursor movement and clearing, and doesn't depend
+    /// on a terminfo database, so it also works in minimal containers. `from_name` falls back to
+    /// it for ANSI-like terminals (`xterm*`, `screen*`, `tmux*`, ...) without an entry.
+    pub fn ansi() -> TermInfo {
+        let mut bools = HashMap::new();
+        for name in ["am", "bce", "msgr", "xenl"] {
+            bools.insert(name, true);
+        }
+
+        let mut numbers = HashMap::new();
+        numbers.insert("colors", 256);
+        numbers.insert("pairs", 65536);
+        numbers.insert("cols", 80);
+        numbers.insert("lines", 24);
+        numbers.insert("it", 8);
+
+        let strings: HashMap<_, _> = [
+            ("bel", &b"\x07"[..]),
+            ("cr", b"\r"),
+            ("ind", b"\n"),
+            ("cud1", b"\n"),
+            ("cub1", b"\x08"),
+            ("cuf1", b"\x1B[C"),
+            ("cuu1", b"\x1B[A"),
+            ("cub", b"\x1B[%p1%dD"),
+            ("cud", b"\x1B[%p1%dB"),
+            ("cuf", b"\x1B[%p1%dC"),
+            ("cuu", b"\x1B[%p1%dA"),
+            ("cup", b"\x1B[%i%p1%d;%p2%dH"),
+            ("home", b"\x1B[H"),
+            ("sc", b"\x1B7"),
+            ("rc", b"\x1B8"),
+            ("civis", b"\x1B[?25l"),
+            ("cnorm", b"\x1B[?25h"),
+            ("clear", b"\x1B[H\x1B[2J"),
+            ("ed", b"\x1B[J"),
+            ("el", b"\x1B[K"),
+            ("el1", b"\x1B[1K"),
+            ("dl1", b"\x1B[M"),
+            ("sgr0", b"\x1B[0m"),
+            ("bold", b"\x1B[1m"),
+            ("dim", b"\x1B[2m"),
+            ("sitm", b"\x1B[3m"),
+            ("ritm", b"\x1B[23m"),
+            ("smul", b"\x1B[4m"),
+            ("rmul", b"\x1B[24m"),
+            ("blink", b"\x1B[5m"),
+            ("rev", b"\x1B[7m"),
+            ("smso", b"\x1B[7m"),
+            ("rmso", b"\x1B[27m"),
+            ("invis", b"\x1B[8m"),
+            ("op", ANSI_OP),
+            ("setaf", ANSI_SETAF),
+            ("setab", ANSI_SETAB),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name, value.to_vec()))
+        .collect();
+
+        TermInfo {
+            names: vec![
+                "xterm-256color".to_owned(),
+                "generic 256-color ANSI terminal".to_owned(),
+            ],
+            bools,
+            numbers,
+            strings,
+        }
+    }
+
     /// Parse the given `TermInfo`.
+    ///
+    /// With the `flate2` feature, files with a `.gz` extension are decompressed first.
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<TermInfo> {
         Self::_from_path(path.as_ref())
     }
@@ -133,11 +374,111 @@ impl TermInfo {
     // us. Alas. )
     fn _from_path(path: &Path) -> Result<TermInfo> {
         let file = File::open(path).map_err(crate::Error::Io)?;
-        let mut reader = BufReader::new(file);
+        #[cfg(feature = "flate2")]
+        {
+            if path.extension().is_some_and(|ext| ext == "gz") {
+                let file = flate2::read::GzDecoder::new(BufReader::new(file));
+                return TermInfo::from_reader(&mut BufReader::new(file));
+            }
+        }
+        TermInfo::from_reader(&mut BufReader::new(file))
+    }
+
+    /// Parse a compiled `TermInfo` entry from `reader`.
+    ///
+    /// The reader should be buffered, as the entry is read in small pieces.
+    pub fn from_reader<R: Read + ?Sized>(reader: &mut R) -> Result<TermInfo> {
+        // `&mut R` is itself a reader, which lets unsized readers become a trait object.
+        let mut reader = reader;
         parse(&mut reader, false)
     }
 
+    /// Parse a `TermInfo` in the source format, e.g. as printed by `infocmp`.
+    ///
+    /// See `parser::source::parse` for the limitations.
+    pub fn from_source(text: &str) -> Result<TermInfo> {
+        parser::source::parse(text)
+    }
+
+    /// Returns whether the entry has each of the named capabilities, of any type.
+    ///
+    /// ```no_run
+    /// # let ti = term::terminfo::TermInfo::from_env().unwrap();
+    /// let [italic, underline] = ti.has_caps(["sitm", "smul"]);
+    /// if !italic && underline {
+    ///     // emphasize with underline instead
+    /// }
+    /// ```
+    pub fn has_caps<const N: usize>(&self, names: [&str; N]) -> [bool; N] {
+        names.map(|name| {
+            self.strings.contains_key(name) || self.numbers.contains_key(name) || self.flag(name)
+        })
+    }
+
+    /// Returns true if the terminal has automatic margins (the `am` capability).
+    pub fn auto_margins(&self) -> bool {
+        self.flag("am")
+    }
+
+    /// Returns true if the terminal has a status line (the `hs` capability).
+    pub fn has_status_line(&self) -> bool {
+        self.flag("hs")
+    }
+
+    /// Returns true if erasing fills the cleared area with the current background color (the `bce`
+    /// capability).
+    pub fn back_color_erase(&self) -> bool {
+        self.flag("bce")
+    }
+
+    /// Returns true if it's safe to move the cursor while in standout mode (the `msgr`
+    /// capability).
+    pub fn move_standout_mode(&self) -> bool {
+        self.flag("msgr")
+    }
+
+    /// Returns the number of colors the terminal supports (the `colors` capability), or 0 if
+    /// unknown.
+    pub fn colors(&self) -> u32 {
+        self.numbers.get("colors").copied().unwrap_or(0)
+    }
+
+    /// Returns the width of the screen in columns (the `cols` capability).
+    pub fn columns(&self) -> Option<u32> {
+        self.numbers.get("cols").copied()
+    }
+
+    /// Returns the height of the screen in lines (the `lines` capability).
+    pub fn lines(&self) -> Option<u32> {
+        self.numbers.get("lines").copied()
+    }
+
+    /// Returns the maximum number of color pairs on the screen (the `pairs` capability).
+    pub fn max_pairs(&self) -> Option<u32> {
+        self.numbers.get("pairs").copied()
+    }
+
+    /// Boolean capabilities are false when absent.
+    fn flag(&self, name: &str) -> bool {
+        self.bools.get(name).copied().unwrap_or(false)
+    }
+
+    /// Compares the capabilities of two entries, like `infocmp -d`.
+    pub fn diff(&self, other: &TermInfo) -> TermInfoDiff {
+        let mut diff = TermInfoDiff::default();
+        diff.add(&self.bools, &other.bools);
+        diff.add(&self.numbers, &other.numbers);
+        diff.add(&self.strings, &other.strings);
+        diff.only_in_self.sort_unstable();
+        diff.only_in_other.sort_unstable();
+        diff.differing.sort_unstable();
+        diff
+    }
+
     /// Retrieve a capability `cmd` and expand it with `params`, writing result to `out`.
+    ///
+    /// Returns `Error::NotSupported` if the capability is absent, and
+    /// `Error::ParameterizedExpansion` if it is present but malformed.
     pub fn apply_cap(&self, cmd: &str, params: &[Param], out: &mut dyn io::Write) -> Result<()> {
         match self.strings.get(cmd) {
             Some(cmd) => match expand(cmd, params, &mut Variables::new()) {
@@ -151,28 +492,107 @@ impl TermInfo {
         }
     }
 
-    /// Write the reset string to `out`.
-    pub fn reset(&self, out: &mut dyn io::Write) -> Result<()> {
-        // are there any terminals that have color/attrs and not sgr0?
-        // Try falling back to sgr, then op
-        let cmd = match [
-            ("sgr0", &[] as &[Param]),
-            ("sgr", &[Param::Number(0)]),
-            ("op", &[]),
-        ]
-        .iter()
-        .filter_map(|&(cap, params)| self.strings.get(cap).map(|c| (c, params)))
-        .next()
-        {
-            Some((op, params)) => match expand(op, params, &mut Variables::new()) {
-                Ok(cmd) => cmd,
-                Err(e) => return Err(e.into()),
-            },
-            None => return Err(crate::Error::NotSupported),
+    /// Like `apply_cap`, but honors padding specifications (`$<5>`, ...) as ncurses does for a
+    /// line running at `baud_rate` bits per second.
+    ///
+    /// Padding is sent from the `pb` baud rate up on terminals without flow control (`xon`), and
+    /// always for mandatory padding (`$<5/>`), `bel` and `flash`. It's sent as the `pad`
+    /// character (NUL by default), or on terminals without one (`npc`) by flushing `out` and
+    /// sleeping. Proportional padding (`$<5*>`) is sent for one line.
+    pub fn apply_cap_with_padding(
+        &self,
+        cmd: &str,
+        params: &[Param],
+        baud_rate: u32,
+        out: &mut dyn io::Write,
+    ) -> Result<()> {
+        let cap = self.strings.get(cmd).ok_or(crate::Error::NotSupported)?;
+        let always = cmd == "bel" || cmd == "flash";
+        let normal = !self.flag("xon") && self.numbers.get("pb").is_some_and(|&pb| baud_rate >= pb);
+        let pad_char = match self.strings.get("pad") {
+            _ if self.flag("npc") => None,
+            Some(pad) => pad.first().copied(),
+            None => Some(0),
         };
-        out.write_all(&cmd)?;
+        let mut written = Ok(());
+        let s = expand_with_padding(cap, params, &mut Variables::new(), &mut |output, delay| {
+            if !(always || normal || delay.is_mandatory()) || written.is_err() {
+                return;
+            }
+            match pad_char {
+                Some(c) => {
+                    // ncurses counts 9 bits per character.
+                    let count = delay.duration().as_micros() * u128::from(baud_rate) / 9_000_000;
+                    output.extend(std::iter::repeat(c).take(count as usize));
+                }
+                None => {
+                    written = out.write_all(output).and_then(|()| out.flush());
+                    output.clear();
+                    thread::sleep(delay.duration());
+                }
+            }
+        })?;
+        written?;
+        out.write_all(&s)?;
         Ok(())
     }
+
+    /// Returns the capability `reset` writes.
+    fn reset_cap(&self) -> Option<&'static str> {
+        // are there any terminals that have color/attrs and not sgr0?
+        // Try falling back to sgr (with every parameter 0), then op
+        ["sgr0", "sgr", "op"]
+            .into_iter()
+            .find(|&cap| self.strings.contains_key(cap))
+    }
+
+    /// Write the reset string to `out`.
+    pub fn reset(&self, out: &mut dyn io::Write) -> Result<()> {
+        match self.reset_cap() {
+            Some(cap) => self.apply_cap(cap, &[], out),
+            None => Err(crate::Error::NotSupported),
+        }
+    }
+}
+
+/// The differences between two `TermInfo` entries, as returned by `TermInfo::diff`.
+///
+/// Capability names are sorted, so the result is stable.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TermInfoDiff {
+    /// Capabilities present only in the entry `diff` was called on
+    pub only_in_self: Vec<&'static str>,
+    /// Capabilities present only in the entry passed to `diff`
+    pub only_in_other: Vec<&'static str>,
+    /// Capabilities present in both entries, but with different values
+    pub differing: Vec<&'static str>,
+}
+
+impl TermInfoDiff {
+    /// Returns true if the two entries have the same capabilities.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.differing.is_empty()
+    }
+
+    fn add<V: PartialEq>(
+        &mut self,
+        ours: &HashMap<&'static str, V>,
+        theirs: &HashMap<&'static str, V>,
+    ) {
+        for (&name, value) in ours {
+            match theirs.get(name) {
+                None => self.only_in_self.push(name),
+                Some(v) if v != value => self.differing.push(name),
+                Some(_) => {}
+            }
+        }
+        self.only_in_other.extend(
+            theirs
+                .keys()
+                .filter(|name| !ours.contains_key(*name))
+                .copied(),
+        );
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -202,6 +622,9 @@ pub enum Error {
     NamesMissingNull,
     /// The strings table was missing a trailing null terminator.
     StringsMissingNull,
+    /// The file is too short to contain the sections its header describes, or an offset points
+    /// outside of its section.
+    MalformedTerminfo(&'static str),
 }
 
 impl ::std::fmt::Display for Error {
@@ -216,6 +639,7 @@ impl ::std::fmt::Display for Error {
             NotUtf8(e) => e.fmt(f),
             NamesMissingNull => f.write_str("names table missing NUL terminator"),
             StringsMissingNull => f.write_str("string table missing NUL terminator"),
+            MalformedTerminfo(msg) => write!(f, "malformed terminfo: {}", msg),
         }
     }
 }
@@ -242,13 +666,16 @@ pub mod parser {
     /// ncurses-compatible compiled terminfo format parsing (term(5))
     pub mod compiled;
     mod names;
+    /// terminfo source format parsing (terminfo(5))
+    pub mod source;
 }
 pub mod parm;
 
 fn cap_for_attr(attr: Attr) -> &'static str {
     match attr {
-        Attr::Bold => "bold",
-        Attr::Dim => "dim",
+        // There are no capabilities for turning these off; see `TerminfoTerminal::attr`.
+        Attr::Bold(_) => "bold",
+        Attr::Dim(_) => "dim",
         Attr::Italic(true) => "sitm",
         Attr::Italic(false) => "ritm",
         Attr::Underline(true) => "smul",
@@ -258,139 +685,2267 @@ fn cap_for_attr(attr: Attr) -> &'static str {
         Attr::Standout(false) => "rmso",
         Attr::Reverse => "rev",
         Attr::Secure => "invis",
+        Attr::Protected(_) => "prot",
         Attr::ForegroundColor(_) => "setaf",
         Attr::BackgroundColor(_) => "setab",
     }
 }
 
-/// A Terminal that knows how many colors it supports, with a reference to its
-/// parsed Terminfo database record.
-#[derive(Clone, Debug)]
-pub struct TerminfoTerminal<T> {
-    num_colors: u32,
-    out: T,
-    ti: TermInfo,
+/// A key on the keyboard, for use with `TerminfoTerminal::key_sequence` and
+/// `TerminfoTerminal::read_key`.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Key {
+    /// A key that produces text
+    Char(char),
+    /// Ctrl and a key, e.g. `Ctrl('c')`, for the control codes not covered by another variant
+    Ctrl(char),
+    /// Alt (or Meta) and a key that produces text, or `Alt('\x1B')` for Alt and Escape (which
+    /// is also what pressing Escape twice quickly looks like)
+    Alt(char),
+    /// Enter (or Return)
+    Enter,
+    /// Tab
+    Tab,
+    /// Escape
+    Escape,
+    /// Up arrow
+    Up,
+    /// Down arrow
+    Down,
+    /// Left arrow
+    Left,
+    /// Right arrow
+    Right,
+    /// Home
+    Home,
+    /// End
+    End,
+    /// Page up
+    PageUp,
+    /// Page down
+    PageDown,
+    /// Insert
+    Insert,
+    /// Delete
+    Delete,
+    /// Backspace
+    Backspace,
+    /// Shift+Tab
+    BackTab,
+    /// Function key `n`, from F0 to F63
+    F(u8),
 }
 
-impl<T: Write> Terminal for TerminfoTerminal<T> {
-    type Output = T;
-    fn fg(&mut self, color: color::Color) -> Result<()> {
-        let color = self.dim_if_necessary(color);
-        if self.num_colors > color {
-            return self
-                .ti
-                .apply_cap("setaf", &[Param::Number(color as i32)], &mut self.out);
+/// A line-drawing character, for use with `TerminfoTerminal::line_char`.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum LineChar {
+    /// `─`
+    Horizontal,
+    /// `│`
+    Vertical,
+    /// `┌`
+    TopLeft,
+    /// `┐`
+    TopRight,
+    /// `└`
+    BottomLeft,
+    /// `┘`
+    BottomRight,
+    /// `├`
+    TeeLeft,
+    /// `┤`
+    TeeRight,
+    /// `┬`
+    TeeTop,
+    /// `┴`
+    TeeBottom,
+    /// `┼`
+    Cross,
+}
+
+impl LineChar {
+    /// Returns the VT100 alternate character set code, the Unicode box-drawing character and
+    /// the ASCII approximation for this character.
+    fn chars(self) -> (u8, char, char) {
+        match self {
+            LineChar::Horizontal => (b'q', '\u{2500}', '-'),
+            LineChar::Vertical => (b'x', '\u{2502}', '|'),
+            LineChar::TopLeft => (b'l', '\u{250C}', '+'),
+            LineChar::TopRight => (b'k', '\u{2510}', '+'),
+            LineChar::BottomLeft => (b'm', '\u{2514}', '+'),
+            LineChar::BottomRight => (b'j', '\u{2518}', '+'),
+            LineChar::TeeLeft => (b't', '\u{251C}', '+'),
+            LineChar::TeeRight => (b'u', '\u{2524}', '+'),
+            LineChar::TeeTop => (b'w', '\u{252C}', '+'),
+            LineChar::TeeBottom => (b'v', '\u{2534}', '+'),
+            LineChar::Cross => (b'n', '\u{253C}', '+'),
         }
-        Err(crate::Error::ColorOutOfRange)
     }
+}
 
-    fn bg(&mut self, color: color::Color) -> Result<()> {
-        let color = self.dim_if_necessary(color);
-        if self.num_colors > color {
-            return self
-                .ti
-                .apply_cap("setab", &[Param::Number(color as i32)], &mut self.out);
+/// The shape of the cursor, for use with `TerminfoTerminal::set_cursor_shape`.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum CursorShape {
+    /// Blinking block
+    BlinkingBlock = 1,
+    /// Steady block
+    SteadyBlock = 2,
+    /// Blinking underline
+    BlinkingUnderline = 3,
+    /// Steady underline
+    SteadyUnderline = 4,
+    /// Blinking vertical bar
+    BlinkingBar = 5,
+    /// Steady vertical bar
+    SteadyBar = 6,
+}
+
+/// Flags for `TerminfoTerminal::push_keyboard_enhancement`, from the kitty keyboard protocol.
+///
+/// Combine them with `|`.
+pub mod keyboard {
+    /// Report keys that would otherwise be ambiguous (such as Escape or Alt+key) with escape codes
+    pub const DISAMBIGUATE_ESCAPE_CODES: u8 = 1;
+    /// Report key repeat and release events as well as presses
+    pub const REPORT_EVENT_TYPES: u8 = 2;
+    /// Report the shifted and base layout keys along with the key
+    pub const REPORT_ALTERNATE_KEYS: u8 = 4;
+    /// Report all keys, including those that produce text, with escape codes
+    pub const REPORT_ALL_KEYS_AS_ESCAPE_CODES: u8 = 8;
+    /// Report the text a key produces along with its escape code
+    pub const REPORT_ASSOCIATED_TEXT: u8 = 16;
+}
+
+/// A terminal emulator, as identified by `TerminfoTerminal::term_program`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum TermProgram {
+    /// iTerm2
+    ITerm,
+    /// macOS Terminal
+    AppleTerminal,
+    /// The Visual Studio Code integrated terminal
+    VsCode,
+    /// WezTerm
+    WezTerm,
+    /// Ghostty
+    Ghostty,
+    /// Another program, by its `TERM_PROGRAM` name
+    Other(String),
+}
+
+impl TermProgram {
+    fn from_name(name: &str) -> TermProgram {
+        match name {
+            "iTerm.app" => TermProgram::ITerm,
+            "Apple_Terminal" => TermProgram::AppleTerminal,
+            "vscode" => TermProgram::VsCode,
+            "WezTerm" => TermProgram::WezTerm,
+            "ghostty" => TermProgram::Ghostty,
+            name => TermProgram::Other(name.to_owned()),
         }
-        Err(crate::Error::ColorOutOfRange)
     }
+}
 
-    fn attr(&mut self, attr: Attr) -> Result<()> {
-        match attr {
-            Attr::ForegroundColor(c) => self.fg(c),
-            Attr::BackgroundColor(c) => self.bg(c),
-            _ => self.ti.apply_cap(cap_for_attr(attr), &[], &mut self.out),
+/// A version number such as `3.4.19`, as returned by `TerminfoTerminal::term_program_version`.
+///
+/// Versions compare component by component, so `3.10` is newer than `3.9`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct Version(Vec<u32>);
+
+impl Version {
+    /// Parses the numeric components of `version` separated by dots, up to the first one that
+    /// isn't a number; the digits it starts with still count.
+    ///
+    /// For example, WezTerm's `20230712-072601-f4abf8fd` is read as `20230712`. Trailing zeros
+    /// are dropped, so `3.4.0` equals `3.4`. Returns `None` if `version` doesn't start with a
+    /// digit.
+    pub fn parse(version: &str) -> Option<Version> {
+        let mut parts = Vec::new();
+        for part in version.split('.') {
+            let digits = part.bytes().take_while(u8::is_ascii_digit).count();
+            match part[..digits].parse() {
+                Ok(n) => parts.push(n),
+                Err(_) => break,
+            }
+            if digits < part.len() {
+                break;
+            }
+        }
+        while parts.last() == Some(&0) && parts.len() > 1 {
+            parts.pop();
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(Version(parts))
         }
     }
+}
 
-    fn supports_attr(&self, attr: Attr) -> bool {
-        match attr {
-            Attr::ForegroundColor(_) | Attr::BackgroundColor(_) => self.num_colors > 0,
-            _ => {
-                let cap = cap_for_attr(attr);
-                self.ti.strings.contains_key(cap)
+/// A terminal multiplexer, as detected by `TerminfoTerminal::multiplexer`.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Multiplexer {
+    /// tmux
+    Tmux,
+    /// GNU Screen
+    Screen,
+}
+
+/// The state of the progress indicator set by `TerminfoTerminal::set_progress`.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum ProgressState {
+    /// No progress; hides the indicator
+    None,
+    /// Progress at the given percentage
+    Normal,
+    /// Progress at the given percentage, shown as failed
+    Error,
+    /// Progress of unknown length
+    Indeterminate,
+    /// Progress at the given percentage, shown as paused
+    Paused,
+}
+
+/// Returns the substitute for an unsupported attribute: `Some(Some(attr))` to use `attr` instead,
+/// `Some(None)` to silently drop it, or `None` if it has no acceptable substitute.
+fn fallback_for_attr(attr: Attr) -> Option<Option<Attr>> {
+    match attr {
+        Attr::Italic(on) => Some(Some(Attr::Underline(on))),
+        Attr::Dim(_) | Attr::Blink => Some(None),
+        // Dropping secure mode would reveal hidden text.
+        _ => None,
+    }
+}
+
+/// Appends the standard (padded) base64 encoding of `data` to `out`.
+fn base64_encode(data: &[u8], out: &mut Vec<u8>) {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize]);
+            } else {
+                out.push(b'=');
             }
         }
     }
+}
 
-    fn reset(&mut self) -> Result<()> {
-        self.ti.reset(&mut self.out)
-    }
+/// The special keys `TerminfoTerminal::read_key` looks up in the terminfo entry, along with the
+/// function keys.
+const SPECIAL_KEYS: &[Key] = &[
+    Key::Up,
+    Key::Down,
+    Key::Left,
+    Key::Right,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+    Key::Insert,
+    Key::Delete,
+    Key::BackTab,
+];
 
-    fn supports_reset(&self) -> bool {
-        ["sgr0", "sgr", "op"]
-            .iter()
-            .any(|&cap| self.ti.strings.contains_key(cap))
+/// Sequences commonly sent for special keys. Terminals send different sequences depending on
+/// whether the keypad is in application mode, and the terminfo entry only describes one of them.
+const FALLBACK_KEY_SEQUENCES: &[(&[u8], Key)] = &[
+    (b"\x1B[A", Key::Up),
+    (b"\x1B[B", Key::Down),
+    (b"\x1B[C", Key::Right),
+    (b"\x1B[D", Key::Left),
+    (b"\x1B[H", Key::Home),
+    (b"\x1B[F", Key::End),
+    (b"\x1BOA", Key::Up),
+    (b"\x1BOB", Key::Down),
+    (b"\x1BOC", Key::Right),
+    (b"\x1BOD", Key::Left),
+    (b"\x1BOH", Key::Home),
+    (b"\x1BOF", Key::End),
+    (b"\x1B[1~", Key::Home),
+    (b"\x1B[2~", Key::Insert),
+    (b"\x1B[3~", Key::Delete),
+    (b"\x1B[4~", Key::End),
+    (b"\x1B[5~", Key::PageUp),
+    (b"\x1B[6~", Key::PageDown),
+    (b"\x1B[7~", Key::Home),
+    (b"\x1B[8~", Key::End),
+    (b"\x1B[Z", Key::BackTab),
+    (b"\x1BOP", Key::F(1)),
+    (b"\x1BOQ", Key::F(2)),
+    (b"\x1BOR", Key::F(3)),
+    (b"\x1BOS", Key::F(4)),
+    (b"\x1B[11~", Key::F(1)),
+    (b"\x1B[12~", Key::F(2)),
+    (b"\x1B[13~", Key::F(3)),
+    (b"\x1B[14~", Key::F(4)),
+    (b"\x1B[15~", Key::F(5)),
+    (b"\x1B[17~", Key::F(6)),
+    (b"\x1B[18~", Key::F(7)),
+    (b"\x1B[19~", Key::F(8)),
+    (b"\x1B[20~", Key::F(9)),
+    (b"\x1B[21~", Key::F(10)),
+    (b"\x1B[23~", Key::F(11)),
+    (b"\x1B[24~", Key::F(12)),
+    // The Linux console
+    (b"\x1B[[A", Key::F(1)),
+    (b"\x1B[[B", Key::F(2)),
+    (b"\x1B[[C", Key::F(3)),
+    (b"\x1B[[D", Key::F(4)),
+    (b"\x1B[[E", Key::F(5)),
+];
+
+/// The longest escape sequence `TerminfoTerminal::read_key` reads before giving up on it.
+const MAX_KEY_SEQUENCE_LEN: usize = 32;
+
+/// Reads the rest of the UTF-8 encoded character starting with `first`, returning `None` if it
+/// isn't valid UTF-8 or the input ends first.
+fn read_utf8_char<R: Read + ?Sized>(first: u8, input: &mut R) -> Result<Option<char>> {
+    let len = match first {
+        0x00..=0x7F => 1,
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => return Ok(None),
+    };
+    let mut buf = [first, 0, 0, 0];
+    for b in &mut buf[1..len] {
+        match read_reply_byte(input)? {
+            Some(byte) => *b = byte,
+            None => return Ok(None),
+        }
     }
+    Ok(std::str::from_utf8(&buf[..len])
+        .ok()
+        .and_then(|s| s.chars().next()))
+}
 
-    fn supports_color(&self) -> bool {
-        self.num_colors > 0 && self.supports_reset()
+/// Reads a single byte of a reply from the terminal, returning `None` on end of input or timeout.
+fn read_reply_byte<R: Read + ?Sized>(input: &mut R) -> Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        return match input.read(&mut byte) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        };
     }
+}
 
-    fn cursor_up(&mut self) -> Result<()> {
-        self.ti.apply_cap("cuu1", &[], &mut self.out)
+/// Parses a primary device attributes reply such as `\x1B[?62;4;22c` into its attributes, the
+/// first of which is the device class.
+fn parse_device_attributes(reply: &[u8]) -> Option<Vec<u32>> {
+    let params = reply.strip_prefix(b"\x1B[?")?.strip_suffix(b"c")?;
+    std::str::from_utf8(params)
+        .ok()?
+        .split(';')
+        .map(|p| p.parse().ok())
+        .collect()
+}
+
+/// Parses an OSC 10/11 color reply such as `\x1B]11;rgb:ffff/8080/0000\x07`.
+fn parse_osc_color_reply(reply: &[u8]) -> Option<(u8, u8, u8)> {
+    let reply = std::str::from_utf8(reply).ok()?;
+    let reply = reply
+        .strip_suffix('\x07')
+        .or_else(|| reply.strip_suffix("\x1B\\"))?;
+    let (_, spec) = reply.split_once(";rgb:")?;
+    // Each component has 1-4 hex digits; scale it down to 8 bits.
+    let component = |s: &str| {
+        if s.is_empty() || s.len() > 4 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let max = (1u32 << (4 * s.len())) - 1;
+        let value = u32::from_str_radix(s, 16).ok()?;
+        Some((value * 255 / max) as u8)
+    };
+    let mut parts = spec.split('/');
+    let rgb = (
+        component(parts.next()?)?,
+        component(parts.next()?)?,
+        component(parts.next()?)?,
+    );
+    match parts.next() {
+        Some(_) => None,
+        None => Some(rgb),
     }
+}
 
-    fn delete_line(&mut self) -> Result<()> {
-        self.ti.apply_cap("el", &[], &mut self.out)
+/// Maps an ANSI color number to the numbering used by `setf`/`setb`, which swaps red and blue
+/// (and therefore yellow and cyan).
+fn ansi_to_legacy_color(color: color::Color) -> color::Color {
+    const LEGACY: [color::Color; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+    (color & !7) | LEGACY[(color & 7) as usize]
+}
+
+/// The writer of a `TerminfoTerminal`, which holds on to output until `capacity` bytes are
+/// pending, see `TerminfoTerminal::set_buffer_capacity`.
+struct Output<T> {
+    // Only `None` once `into_inner` has taken it.
+    inner: Option<T>,
+    pending: Vec<u8>,
+    capacity: usize,
+    // `Drop` can't require `T: Write`, so remember how to write the pending output instead.
+    write_all: fn(&mut T, &[u8]) -> io::Result<()>,
+}
+
+impl<T: Write> Output<T> {
+    fn new(inner: T, capacity: usize) -> Output<T> {
+        Output {
+            inner: Some(inner),
+            pending: Vec::new(),
+            capacity,
+            write_all: |out, buf| out.write_all(buf),
+        }
     }
 
-    fn carriage_return(&mut self) -> Result<()> {
-        self.ti.apply_cap("cr", &[], &mut self.out)
+    /// Writes out the pending output.
+    fn write_pending(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let inner = self.inner.as_mut().expect("output taken");
+        let result = inner.write_all(&self.pending);
+        if result.is_ok() {
+            self.pending.clear();
+        }
+        result
     }
+}
 
+impl<T> Output<T> {
     fn get_ref(&self) -> &T {
-        &self.out
+        self.inner.as_ref().expect("output taken")
     }
 
     fn get_mut(&mut self) -> &mut T {
-        &mut self.out
+        self.inner.as_mut().expect("output taken")
     }
 
-    fn into_inner(self) -> T
-    where
-        Self: Sized,
-    {
-        self.out
+    /// Returns the writer, writing out the pending output first if possible.
+    fn into_inner(mut self) -> T {
+        let mut inner = self.inner.take().expect("output taken");
+        let _ = (self.write_all)(&mut inner, &self.pending);
+        inner
     }
 }
 
-impl<T: Write> TerminfoTerminal<T> {
-    /// Create a new TerminfoTerminal with the given TermInfo and Write.
-    pub fn new_with_terminfo(out: T, ti: TermInfo) -> TerminfoTerminal<T> {
-        let num_colors = if ti.strings.contains_key("setaf") && ti.strings.contains_key("setab") {
-            ti.numbers.get("colors").map_or(0, |&n| n)
+impl<T: Write> Write for Output<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.pending.len() + buf.len() > self.capacity {
+            self.write_pending()?;
+        }
+        if buf.len() >= self.capacity {
+            self.get_mut().write(buf)
         } else {
-            0
-        };
+            self.pending.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
 
-        TerminfoTerminal {
-            out,
-            ti,
-            num_colors,
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        self.get_mut().flush()
+    }
+}
+
+impl<T: Clone> Clone for Output<T> {
+    // The pending output is only written by the original.
+    fn clone(&self) -> Output<T> {
+        Output {
+            inner: self.inner.clone(),
+            pending: Vec::new(),
+            capacity: self.capacity,
+            write_all: self.write_all,
         }
     }
+}
 
-    /// Create a new TerminfoTerminal for the current environment with the given Write.
-    ///
-    /// Returns `None` when the terminfo cannot be found or parsed.
-    pub fn new(out: T) -> Option<TerminfoTerminal<T>> {
-        TermInfo::from_env()
-            .map(move |ti| TerminfoTerminal::new_with_terminfo(out, ti))
-            .ok()
+impl<T> Drop for Output<T> {
+    fn drop(&mut self) {
+        if let Some(inner) = &mut self.inner {
+            let _ = (self.write_all)(inner, &self.pending);
+        }
     }
+}
 
-    fn dim_if_necessary(&self, color: color::Color) -> color::Color {
-        if color >= self.num_colors && (8..16).contains(&color) {
-            color - 8
-        } else {
-            color
+/// The colors and attributes a terminal is known to be set to, used to skip setting them again.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct StyleState {
+    fg: Option<color::Color>,
+    bg: Option<color::Color>,
+    attrs: Vec<Attr>,
+}
+
+impl StyleState {
+    /// Returns true if setting `attr` wouldn't change anything.
+    fn has_attr(&self, attr: Attr) -> bool {
+        match attr {
+            Attr::Bold(false) | Attr::Dim(false) => !self
+                .attrs
+                .iter()
+                .any(|a| matches!(a, Attr::Bold(true) | Attr::Dim(true))),
+            Attr::Italic(false) => !self.attrs.contains(&Attr::Italic(true)),
+            Attr::Underline(false) => !self.attrs.contains(&Attr::Underline(true)),
+            Attr::Standout(false) => !self.attrs.contains(&Attr::Standout(true)),
+            Attr::Protected(false) => !self.attrs.contains(&Attr::Protected(true)),
+            attr => self.attrs.contains(&attr),
+        }
+    }
+
+    fn set_attr(&mut self, attr: Attr) {
+        match attr {
+            Attr::Bold(false) | Attr::Dim(false) => self
+                .attrs
+                .retain(|a| !matches!(a, Attr::Bold(true) | Attr::Dim(true))),
+            Attr::Italic(false) => self.attrs.retain(|&a| a != Attr::Italic(true)),
+            Attr::Underline(false) => self.attrs.retain(|&a| a != Attr::Underline(true)),
+            Attr::Standout(false) => self.attrs.retain(|&a| a != Attr::Standout(true)),
+            Attr::Protected(false) => self.attrs.retain(|&a| a != Attr::Protected(true)),
+            attr if !self.attrs.contains(&attr) => self.attrs.push(attr),
+            _ => {}
         }
     }
 }
 
-impl<T: Write> Write for TerminfoTerminal<T> {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.out.write(buf)
+/// Why bold is on, so that bold turned on for a bright color can be turned off again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Bold {
+    /// Off, as far as this terminal knows
+    #[default]
+    Off,
+    /// On only because the foreground color is bright, see `set_bright_via_bold`
+    ForBright,
+    /// Turned on with `attr`
+    Requested,
+}
+
+type UnsupportedCallback = Arc<Mutex<Box<dyn FnMut(&str) + Send>>>;
+
+/// A Terminal that knows how many colors it supports, with a reference to its
+/// parsed Terminfo database record.
+#[derive(Clone)]
+pub struct TerminfoTerminal<T> {
+    num_colors: u32,
+    truecolor: bool,
+    column: ColumnTracker,
+    out: Output<T>,
+    ti: Arc<TermInfo>,
+    attr_fallbacks: bool,
+    bright_via_bold: bool,
+    bold: Bold,
+    auto_flush_attrs: bool,
+    charset_initialized: bool,
+    tmux_passthrough: bool,
+    baud_rate: Option<u32>,
+    /// The sequences `read_key` recognizes, built on first use
+    key_sequences: OnceLock<Vec<(Vec<u8>, Key)>>,
+    unsupported_callback: Option<UnsupportedCallback>,
+    style: Option<StyleState>,
+    environment: Environment,
+}
+
+impl<T> fmt::Debug for TerminfoTerminal<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TerminfoTerminal")
+            .field("name", &self.ti.names.first())
+            .field("num_colors", &self.num_colors)
+            .field("truecolor", &self.truecolor)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Write> Terminal for TerminfoTerminal<T> {
+    type Output = T;
+    fn fg(&mut self, color: color::Color) -> Result<()> {
+        if self.style.as_ref().is_some_and(|s| s.fg == Some(color)) {
+            return Ok(());
+        }
+        let bold = self.bold_for_bright(color);
+        if !bold {
+            self.end_bold_for_bright()?;
+        }
+        let code = self.color_code(true, color)?;
+        self.out.write_all(&code)?;
+        if bold && self.bold == Bold::Off {
+            self.bold = Bold::ForBright;
+        }
+        if let Some(style) = &mut self.style {
+            style.fg = Some(color);
+            if bold {
+                style.set_attr(Attr::Bold(true));
+            }
+        }
+        self.auto_flush()
+    }
+
+    fn bg(&mut self, color: color::Color) -> Result<()> {
+        if self.style.as_ref().is_some_and(|s| s.bg == Some(color)) {
+            return Ok(());
+        }
+        let code = self.color_code(false, color)?;
+        self.out.write_all(&code)?;
+        if let Some(style) = &mut self.style {
+            style.bg = Some(color);
+        }
+        self.auto_flush()
+    }
+
+    fn set_colors(&mut self, fg: color::Color, bg: color::Color) -> Result<()> {
+        if let Some(style) = &self.style {
+            if style.fg == Some(fg) {
+                return self.bg(bg);
+            }
+            if style.bg == Some(bg) {
+                return self.fg(fg);
+            }
+        }
+        let bold = self.bold_for_bright(fg);
+        if !bold {
+            self.end_bold_for_bright()?;
+        }
+        let mut code = self.color_code(true, fg)?;
+        code.extend(self.color_code(false, bg)?);
+        self.out.write_all(&code)?;
+        if bold && self.bold == Bold::Off {
+            self.bold = Bold::ForBright;
+        }
+        if let Some(style) = &mut self.style {
+            style.fg = Some(fg);
+            style.bg = Some(bg);
+            if bold {
+                style.set_attr(Attr::Bold(true));
+            }
+        }
+        self.auto_flush()
+    }
+
+    fn attr(&mut self, attr: Attr) -> Result<()> {
+        match attr {
+            Attr::ForegroundColor(c) => self.fg(c),
+            Attr::BackgroundColor(c) => self.bg(c),
+            // Already on, but now it should stay on.
+            Attr::Bold(true) if self.bold == Bold::ForBright => {
+                self.bold = Bold::Requested;
+                Ok(())
+            }
+            // Terminfo can only turn these off with `sgr`, which sets every attribute at once,
+            // but ANSI terminals have codes for normal intensity and for unprotected text
+            // (DECSCA).
+            Attr::Bold(false) | Attr::Dim(false) | Attr::Protected(false) => {
+                if !self.supports_attr(attr) {
+                    return Err(crate::Error::NotSupported);
+                }
+                if self.has_style_attr(attr) {
+                    return Ok(());
+                }
+                if !self.is_ansi() {
+                    return self.clear_attr_with_sgr(attr);
+                }
+                let code: &[u8] = if attr == Attr::Protected(false) {
+                    b"\x1B[0\"q"
+                } else {
+                    b"\x1B[22m"
+                };
+                self.out.write_all(code)?;
+                let bright = attr != Attr::Protected(false) && self.bold == Bold::ForBright;
+                if attr != Attr::Protected(false) {
+                    self.bold = Bold::Off;
+                }
+                if let Some(style) = &mut self.style {
+                    style.set_attr(attr);
+                    if bright {
+                        // The bright color is now shown as its normal counterpart.
+                        style.fg = None;
+                    }
+                }
+                self.auto_flush()
+            }
+            _ => {
+                let cap = cap_for_attr(attr);
+                if self.attr_fallbacks && !self.ti.strings.contains_key(cap) {
+                    if attr == Attr::Standout(true) && self.ti.strings.contains_key("rev") {
+                        // Most terminals implement standout as reverse video, often with bold.
+                        self.attr(Attr::Reverse)?;
+                        if self.ti.strings.contains_key("bold") {
+                            self.attr(Attr::Bold(true))?;
+                        }
+                        return Ok(());
+                    }
+                    return match fallback_for_attr(attr) {
+                        Some(Some(attr)) => self.attr(attr),
+                        Some(None) => Ok(()),
+                        None => {
+                            self.report_unsupported(cap);
+                            Err(crate::Error::NotSupported)
+                        }
+                    };
+                }
+                // Checked after support, so that the result doesn't depend on what was set
+                // before.
+                if self.ti.strings.contains_key(cap) && self.has_style_attr(attr) {
+                    return Ok(());
+                }
+                self.write_cap(cap, &[])?;
+                if attr == Attr::Bold(true) {
+                    self.bold = Bold::Requested;
+                }
+                if let Some(style) = &mut self.style {
+                    style.set_attr(attr);
+                }
+                self.auto_flush()
+            }
+        }
+    }
+
+    /// Returns true if the terminal itself supports the attribute, e.g. `Attr::Standout(true)`
+    /// only if the terminal has `smso`. Substitutes enabled by `set_attr_fallbacks` don't count.
+    fn supports_attr(&self, attr: Attr) -> bool {
+        match attr {
+            Attr::ForegroundColor(_) => self.num_colors > 0 && self.can_set_color(true),
+            Attr::BackgroundColor(_) => self.num_colors > 0 && self.can_set_color(false),
+            Attr::Bold(false) | Attr::Dim(false) | Attr::Protected(false) => {
+                self.is_ansi() || self.ti.strings.contains_key("sgr")
+            }
+            _ => {
+                let cap = cap_for_attr(attr);
+                self.ti.strings.contains_key(cap)
+            }
+        }
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        let cap = self.ti.reset_cap().ok_or(crate::Error::NotSupported)?;
+        self.write_cap(cap, &[])?;
+        if self.ti.strings.contains_key("sgr0") || self.ti.strings.contains_key("sgr") {
+            self.style = Some(StyleState::default());
+            self.bold = Bold::Off;
+        } else if let Some(style) = &mut self.style {
+            // Only `op`, which resets the colors.
+            style.fg = None;
+            style.bg = None;
+        }
+        self.auto_flush()
+    }
+
+    fn supports_reset(&self) -> bool {
+        ["sgr0", "sgr", "op"]
+            .iter()
+            .any(|&cap| self.ti.strings.contains_key(cap))
+    }
+
+    fn supports_color(&self) -> bool {
+        self.num_colors > 0 && self.supports_reset()
+    }
+
+    fn supports_color_index(&self, color: color::Color) -> bool {
+        self.num_colors > self.dim_if_necessary(color)
+    }
+
+    fn truecolor(&self) -> bool {
+        self.truecolor
+    }
+
+    fn cursor_up(&mut self) -> Result<()> {
+        self.write_cap("cuu1", &[])
+    }
+
+    fn delete_line(&mut self) -> Result<()> {
+        self.write_cap("el", &[])
+    }
+
+    fn carriage_return(&mut self) -> Result<()> {
+        self.write_cap("cr", &[])?;
+        self.column.line_start();
+        Ok(())
+    }
+
+    fn begin_sync_update(&mut self) -> Result<()> {
+        self.sync_update(true)
+    }
+
+    fn end_sync_update(&mut self) -> Result<()> {
+        self.sync_update(false)
+    }
+
+    fn hyperlink(&mut self, url: &str, text: &str) -> Result<()> {
+        if !self.is_ansi() {
+            return Err(crate::Error::NotSupported);
+        }
+        if url.chars().any(char::is_control) {
+            return Err(crate::Error::InvalidControlCharacter);
+        }
+        self.write_osc(format!("\x1B]8;;{}\x1B\\", url).as_bytes())?;
+        self.write_text(text.as_bytes())?;
+        self.write_osc(b"\x1B]8;;\x1B\\")
+    }
+
+    fn term_name(&self) -> Option<&str> {
+        self.ti.names.first().map(|s| &s[..])
+    }
+
+    fn get_ref(&self) -> &T {
+        self.out.get_ref()
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        self.out.get_mut()
+    }
+
+    fn into_inner(self) -> T
+    where
+        Self: Sized,
+    {
+        self.out.into_inner()
+    }
+}
+
+impl<T: Write> TerminfoTerminal<T> {
+    /// Create a new TerminfoTerminal with the given TermInfo and Write.
+    ///
+    /// Pass an `Arc<TermInfo>` to share one parsed entry between several terminals.
+    ///
+    /// Colors are enabled (up to the `colors` capability) if the terminal can set either the
+    /// foreground or the background color:
+    ///
+    /// | Capabilities         | `fg`                    | `bg`                    |
+    /// |----------------------|-------------------------|-------------------------|
+    /// | `setaf` and `setab`  | `setaf`                 | `setab`                 |
+    /// | `setf` and `setb`    | `setf`                  | `setb`                  |
+    /// | `setaf` (or `setf`)  | `setaf` (or `setf`)     | `Err(NotSupported)`     |
+    /// | `setab` (or `setb`)  | `Err(NotSupported)`     | `setab` (or `setb`)     |
+    /// | none                 | `Err(ColorOutOfRange)`  | `Err(ColorOutOfRange)`  |
+    ///
+    /// `setaf`/`setab` are preferred where both they and `setf`/`setb` are present.
+    ///
+    /// The terminal is considered to support 24-bit color (see `Terminal::truecolor`) if the entry
+    /// has the extended `RGB` or `Tc` flag, or both the `setrgbf` and `setrgbb` capabilities.
+    ///
+    /// The environment variables the terminal consults (see `set_environment`) are read from the
+    /// process.
+    pub fn new_with_terminfo<I: Into<Arc<TermInfo>>>(out: T, ti: I) -> TerminfoTerminal<T> {
+        TerminfoTerminal::with_environment(out, ti.into(), Environment::from_process())
+    }
+
+    fn with_environment(
+        out: T,
+        ti: Arc<TermInfo>,
+        environment: Environment,
+    ) -> TerminfoTerminal<T> {
+        let has_any = |caps: [&str; 4]| caps.iter().any(|&cap| ti.strings.contains_key(cap));
+        let num_colors = if has_any(["setaf", "setab", "setf", "setb"]) {
+            ti.colors()
+        } else {
+            0
+        };
+        let truecolor = ti.flag("RGB")
+            || ti.flag("Tc")
+            || (ti.strings.contains_key("setrgbf") && ti.strings.contains_key("setrgbb"));
+
+        TerminfoTerminal {
+            out: Output::new(out, 0),
+            ti,
+            num_colors,
+            truecolor,
+            column: ColumnTracker::default(),
+            unsupported_callback: None,
+            style: None,
+            attr_fallbacks: false,
+            bright_via_bold: false,
+            bold: Bold::Off,
+            auto_flush_attrs: false,
+            charset_initialized: false,
+            tmux_passthrough: false,
+            baud_rate: None,
+            key_sequences: OnceLock::new(),
+            environment,
+        }
+    }
+
+    /// Create a new TerminfoTerminal for the current environment with the given Write.
+    ///
+    /// Returns `None` when the terminfo cannot be found or parsed.
+    ///
+    /// If `COLORTERM` indicates that the terminal supports more colors than its terminfo entry
+    /// declares (as is common inside `screen` and `tmux`), 256 colors are used instead. A
+    /// `COLORTERM` of `truecolor` or `24bit` also marks ANSI terminals as supporting 24-bit
+    /// color.
+    ///
+    /// If the entry has no colors at all but `COLORTERM` is set, as happens when `TERM` names a
+    /// colorless entry inside containers and CI, colors are set with the standard ANSI sequences
+    /// (see `set_ansi_colors`): 256 of them for a `COLORTERM` of `truecolor`, `24bit` or
+    /// `256color`, and 8 otherwise.
+    ///
+    /// The entry is only parsed once: later calls share it as long as the variables that locate
+    /// it (see `Environment`) stay the same. Use `term::clear_terminfo_cache` to read it again.
+    pub fn new(out: T) -> Option<TerminfoTerminal<T>> {
+        TerminfoTerminal::from_environment(out, &Environment::from_process())
+    }
+
+    /// Create a new TerminfoTerminal like `new`, but for the given environment rather than that
+    /// of the process.
+    pub fn from_environment(out: T, environment: &Environment) -> Option<TerminfoTerminal<T>> {
+        let ti = cached_from_environment(environment).ok()?;
+        let mut term = TerminfoTerminal::with_environment(out, ti, environment.clone());
+        let colorterm = environment.colorterm.clone().unwrap_or_default();
+        if term.num_colors == 0 && !colorterm.is_empty() {
+            let num_colors = match &colorterm[..] {
+                "truecolor" | "24bit" | "256color" => 256,
+                _ => 8,
+            };
+            term.set_ansi_colors(num_colors);
+        }
+        if matches!(&colorterm[..], "truecolor" | "24bit" | "256color")
+            && term.num_colors > 0
+            && term.num_colors < 256
+            && term.is_ansi()
+        {
+            term.set_num_colors(256);
+        }
+        if matches!(&colorterm[..], "truecolor" | "24bit") && term.is_ansi() {
+            term.truecolor = true;
+        }
+        Some(term)
+    }
+
+    /// Create a new TerminfoTerminal for the current environment that buffers its output.
+    ///
+    /// Text and escape sequences are collected in a `BufWriter` and only reach `out` when the
+    /// buffer fills up, when `flush` is called, or when the terminal is dropped. Call `flush`
+    /// once a frame has been drawn, and before anything that reads the terminal's state (such as
+    /// its cursor position) or writes to `out` through another handle.
+    ///
+    /// Returns `None` when the terminfo cannot be found or parsed.
+    pub fn new_buffered(out: T) -> Option<TerminfoTerminal<BufWriter<T>>> {
+        TerminfoTerminal::new(BufWriter::new(out))
+    }
+
+    /// Create a new TerminfoTerminal that always emits ANSI escape sequences, whatever the
+    /// environment.
+    ///
+    /// This uses `TermInfo::ansi`, a built-in description of a 256-color `xterm`-like terminal,
+    /// instead of the terminfo database, so the output is the same on every host. It's meant for
+    /// capturing colored output, e.g. into a `Vec<u8>`, rather than for driving the current
+    /// terminal.
+    pub fn ansi(out: T) -> TerminfoTerminal<T> {
+        TerminfoTerminal::new_with_terminfo(out, TermInfo::ansi())
+    }
+
+    /// Asks the terminal for its background color (using OSC 11) and reads the reply from `input`.
+    ///
+    /// `input` must be connected to the same terminal, which should be in raw mode so the reply
+    /// isn't echoed or line-buffered. As terminals that don't understand the query never reply,
+    /// `input` should also time out (e.g. using `VMIN`/`VTIME`); a timeout or end of input yields
+    /// `Ok(None)`, as does a reply that can't be parsed.
+    pub fn query_background<R: Read>(&mut self, input: &mut R) -> Result<Option<(u8, u8, u8)>> {
+        if !self.is_ansi() {
+            return Err(crate::Error::NotSupported);
+        }
+        self.out.write_all(b"\x1B]11;?\x1B\\")?;
+        self.out.flush()?;
+
+        // Read up to the terminating BEL or ST; replies are short, so give up on anything long.
+        let mut reply = Vec::new();
+        while reply.len() < 64 {
+            match read_reply_byte(input)? {
+                Some(b) => reply.push(b),
+                None => return Ok(None),
+            }
+            if reply.ends_with(b"\x07") || reply.ends_with(b"\x1B\\") {
+                return Ok(parse_osc_color_reply(&reply));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns whether the terminal can display sixel graphics.
+    ///
+    /// This asks for the terminal's primary device attributes (DA1) and reads the reply from
+    /// `input`, looking for the sixel attribute (4). As with `query_background`, `input` must be
+    /// connected to the same terminal, which should be in raw mode, and should time out. If
+    /// there's no usable reply, this guesses from the terminal's name (`foot` and `mlterm`
+    /// support sixel) and `TERM_PROGRAM` (WezTerm does), returning `Ok(false)` otherwise.
+    pub fn supports_sixel<R: Read>(&mut self, input: &mut R) -> Result<bool> {
+        let name = self.ti.names.first().map_or("", |name| &name[..]);
+        let sixel_name = name.starts_with("foot") || name.starts_with("mlterm");
+        if !self.is_ansi() && !sixel_name {
+            return Err(crate::Error::NotSupported);
+        }
+        self.out.write_all(b"\x1B[c")?;
+        self.out.flush()?;
+
+        let mut reply = Vec::new();
+        while reply.len() < 128 {
+            match read_reply_byte(input)? {
+                Some(b) => reply.push(b),
+                None => break,
+            }
+            if reply.ends_with(b"c") {
+                if let Some(attrs) = parse_device_attributes(&reply) {
+                    return Ok(attrs.iter().skip(1).any(|&attr| attr == 4));
+                }
+                break;
+            }
+        }
+
+        Ok(sixel_name || self.term_program() == Some(TermProgram::WezTerm))
+    }
+
+    /// Sends the `request` capability and reads a reply in the format of the `response`
+    /// capability from `input`, returning the numbers it contains.
+    ///
+    /// For example, `query(input, "u7", "u6")` asks for the cursor position. The response format
+    /// may contain `%d` for a number, `%i` to make the first two numbers 1-based (they are
+    /// returned 0-based), and `%%` for a literal `%`.
+    ///
+    /// As with `query_background`, `input` must be connected to the same terminal, which should
+    /// be in raw mode, and should time out. A timeout, end of input, or a reply that doesn't match
+    /// the format yields `Ok(None)`.
+    pub fn query<R: Read>(
+        &mut self,
+        input: &mut R,
+        request: &str,
+        response: &str,
+    ) -> Result<Option<Vec<u32>>> {
+        let format = match self.ti.strings.get(response) {
+            Some(format) => format.clone(),
+            None => return Err(crate::Error::NotSupported),
+        };
+        self.write_cap(request, &[])?;
+        self.out.flush()?;
+
+        let mut numbers = Vec::new();
+        let mut one_based = false;
+        // A byte read past the end of a number, which must match the next part of the format.
+        let mut pending = None;
+        let mut next_byte = |pending: &mut Option<u8>| match pending.take() {
+            Some(b) => Ok(Some(b)),
+            None => read_reply_byte(input),
+        };
+        let mut i = 0;
+        while i < format.len() {
+            let expected = match (format[i], format.get(i + 1)) {
+                (b'%', Some(b'i')) => {
+                    one_based = true;
+                    i += 2;
+                    continue;
+                }
+                (b'%', Some(b'd')) => {
+                    let mut n: Option<u32> = None;
+                    while let Some(b) = next_byte(&mut pending)? {
+                        match (b as char).to_digit(10) {
+                            Some(d) => match n.unwrap_or(0).checked_mul(10) {
+                                Some(m) => n = m.checked_add(d),
+                                None => return Ok(None),
+                            },
+                            None => {
+                                pending = Some(b);
+                                break;
+                            }
+                        }
+                    }
+                    match n {
+                        Some(n) => numbers.push(n),
+                        None => return Ok(None),
+                    }
+                    i += 2;
+                    continue;
+                }
+                (b'%', Some(b'%')) => {
+                    i += 2;
+                    b'%'
+                }
+                (b'%', c) => {
+                    let c = c.map_or('%', |&c| c as char);
+                    return Err(parm::Error::UnrecognizedFormatOption(c).into());
+                }
+                (b, _) => {
+                    i += 1;
+                    b
+                }
+            };
+            if next_byte(&mut pending)? != Some(expected) {
+                return Ok(None);
+            }
+        }
+        if one_based {
+            for n in numbers.iter_mut().take(2) {
+                *n = n.saturating_sub(1);
+            }
+        }
+        Ok(Some(numbers))
+    }
+
+    /// Copies `data` to the system clipboard (using OSC 52).
+    ///
+    /// This uses the extended `Ms` capability when the terminal's entry has it, and a plain OSC 52
+    /// sequence on other ANSI terminals.
+    ///
+    /// This works over SSH, as the terminal rather than the host sets the clipboard. Many
+    /// terminals ignore the request unless the user has allowed it, and limit the size of the
+    /// payload (e.g. 74994 bytes of encoded data for xterm, 1 MB for kitty); large payloads may
+    /// be truncated or dropped.
+    pub fn copy_to_clipboard(&mut self, data: &[u8]) -> Result<()> {
+        let mut encoded = Vec::new();
+        base64_encode(data, &mut encoded);
+        let mut code = Vec::new();
+        if self.ti.strings.contains_key("Ms") {
+            let params = [
+                Param::Words("c".to_owned()),
+                Param::Words(String::from_utf8_lossy(&encoded).into_owned()),
+            ];
+            self.apply_cap("Ms", &params, &mut code)?;
+        } else if self.is_ansi() {
+            code.extend_from_slice(b"\x1B]52;c;");
+            code.extend(encoded);
+            code.push(b'\x07');
+        } else {
+            self.report_unsupported("Ms");
+            return Err(crate::Error::NotSupported);
+        }
+        self.write_osc(&code)
+    }
+
+    /// Shows `percent` progress in the terminal's progress indicator (using OSC 9;4).
+    ///
+    /// Windows Terminal and ConEmu show this in the taskbar. Most other terminals ignore it,
+    /// though those that use OSC 9 for notifications may show one. `percent` is capped at 100
+    /// and ignored for `ProgressState::None` and `ProgressState::Indeterminate`. Set
+    /// `ProgressState::None` once the work is done.
+    pub fn set_progress(&mut self, state: ProgressState, percent: u8) -> Result<()> {
+        if !self.is_ansi() {
+            return Err(crate::Error::NotSupported);
+        }
+        let state = match state {
+            ProgressState::None => 0,
+            ProgressState::Normal => 1,
+            ProgressState::Error => 2,
+            ProgressState::Indeterminate => 3,
+            ProgressState::Paused => 4,
+        };
+        let code = format!("\x1B]9;4;{};{}\x07", state, percent.min(100));
+        self.write_osc(code.as_bytes())
+    }
+
+    /// Sets the terminal's default foreground color (using OSC 10).
+    pub fn set_default_foreground(&mut self, r: u8, g: u8, b: u8) -> Result<()> {
+        self.set_dynamic_color(10, r, g, b)
+    }
+
+    /// Sets the terminal's default background color (using OSC 11).
+    pub fn set_default_background(&mut self, r: u8, g: u8, b: u8) -> Result<()> {
+        self.set_dynamic_color(11, r, g, b)
+    }
+
+    /// Resets the foreground color to the terminal's default, keeping the background color.
+    ///
+    /// Non-ANSI terminals can only reset both colors at once (with the `op` capability), so this
+    /// resets the background color too on those. Returns `Err(Error::NotSupported)` if the
+    /// terminal can't reset its colors without resetting all attributes.
+    pub fn reset_fg(&mut self) -> Result<()> {
+        self.reset_color(b"\x1B[39m", true)
+    }
+
+    /// Resets the background color to the terminal's default, keeping the foreground color.
+    ///
+    /// Like `reset_fg`, this resets both colors on non-ANSI terminals.
+    pub fn reset_bg(&mut self) -> Result<()> {
+        self.reset_color(b"\x1B[49m", false)
+    }
+
+    /// Redefines the palette entry `color` as the given RGB color.
+    ///
+    /// This uses the `initc` capability when the terminal has it, and OSC 4 on other ANSI
+    /// terminals. Use `reset_palette` to restore the original palette.
+    ///
+    /// Returns `Err(Error::ColorOutOfRange)` if the terminal doesn't have that many colors.
+    pub fn set_palette_color(&mut self, color: color::Color, r: u8, g: u8, b: u8) -> Result<()> {
+        if color >= self.num_colors {
+            return Err(crate::Error::ColorOutOfRange);
+        }
+        let mut code = Vec::new();
+        if self.ti.strings.contains_key("initc") {
+            // initc takes components from 0 to 1000; round up so that terminals scaling them back
+            // down (rounding down) get the original values.
+            let scale = |c: u8| Param::Number((i32::from(c) * 1000 + 254) / 255);
+            let params = [Param::Number(color as i32), scale(r), scale(g), scale(b)];
+            self.apply_cap("initc", &params, &mut code)?;
+        } else if self.is_ansi() {
+            write!(
+                code,
+                "\x1B]4;{};rgb:{:02x}/{:02x}/{:02x}\x1B\\",
+                color, r, g, b
+            )?;
+        } else {
+            self.report_unsupported("initc");
+            return Err(crate::Error::NotSupported);
+        }
+        self.write_osc(&code)
+    }
+
+    /// Restores the terminal's original color palette (the `oc` capability), undoing any changes
+    /// to palette entries, e.g. through OSC 4.
+    ///
+    /// Unlike `reset`, this doesn't change the current colors or attributes.
+    pub fn reset_palette(&mut self) -> Result<()> {
+        self.write_cap("oc", &[])
+    }
+
+    fn reset_color(&mut self, ansi: &[u8], foreground: bool) -> Result<()> {
+        if self.is_ansi() {
+            self.out.write_all(ansi)?;
+            if let Some(style) = &mut self.style {
+                if foreground {
+                    style.fg = None;
+                } else {
+                    style.bg = None;
+                }
+            }
+        } else {
+            self.write_cap("op", &[])?;
+            if let Some(style) = &mut self.style {
+                style.fg = None;
+                style.bg = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_dynamic_color(&mut self, osc: u8, r: u8, g: u8, b: u8) -> Result<()> {
+        if !self.is_ansi() {
+            return Err(crate::Error::NotSupported);
+        }
+        let code = format!("\x1B]{};rgb:{:02x}/{:02x}/{:02x}\x1B\\", osc, r, g, b);
+        self.write_osc(code.as_bytes())
+    }
+
+    /// Writes text (rather than escape sequences), keeping track of the column.
+    fn write_text(&mut self, text: &[u8]) -> io::Result<()> {
+        self.out.write_all(text)?;
+        self.column.track(text, usize::from(self.tab_width()));
+        Ok(())
+    }
+
+    /// Writes an OSC sequence, wrapped for tmux to pass it through if enabled.
+    fn write_osc(&mut self, seq: &[u8]) -> Result<()> {
+        if self.tmux_passthrough && self.multiplexer() == Some(Multiplexer::Tmux) {
+            let mut code = b"\x1BPtmux;".to_vec();
+            for &b in seq {
+                // Escapes within the passthrough sequence must be doubled.
+                if b == b'\x1B' {
+                    code.push(b);
+                }
+                code.push(b);
+            }
+            code.extend_from_slice(b"\x1B\\");
+            self.out.write_all(&code)?;
+        } else {
+            self.out.write_all(seq)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the distance between tab stops, from the `it` capability (8 if it's missing).
+    pub fn tab_width(&self) -> u16 {
+        self.ti
+            .numbers
+            .get("it")
+            .and_then(|&it| u16::try_from(it).ok())
+            .filter(|&it| it > 0)
+            .unwrap_or(8)
+    }
+
+    /// Writes `text`, replacing each tab with spaces up to the next tab stop (see `tab_width`).
+    ///
+    /// This lines tabbed text up the same way on every terminal, whatever its tab stops are set
+    /// to. Tab stops are found from the current column, which the terminal tracks from the text
+    /// written through it (`Write`, and methods such as `hyperlink` and `repeat_char`) since the
+    /// last newline, carriage return, `next_line` or `prev_line`. Other cursor movement and text
+    /// written to the underlying writer directly aren't accounted for.
+    pub fn expand_tabs(&mut self, text: &str) -> io::Result<()> {
+        let tab_width = usize::from(self.tab_width());
+        let mut parts = text.split('\t');
+        if let Some(first) = parts.next() {
+            self.write_all(first.as_bytes())?;
+        }
+        for part in parts {
+            let spaces = tab_width - self.column.column() % tab_width;
+            self.write_all(&vec![b' '; spaces])?;
+            self.write_all(part.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the byte `c` `n` times, using the `rep` capability when the terminal has it.
+    ///
+    /// Falls back to writing the bytes out, so this always succeeds unless writing fails.
+    pub fn repeat_char(&mut self, c: u8, n: u16) -> Result<()> {
+        if n > 1 && self.ti.strings.contains_key("rep") {
+            let params = [Param::Number(i32::from(c)), Param::Number(i32::from(n))];
+            self.write_cap("rep", &params)?;
+            let width = crate::display_width(&[c]);
+            self.column.advance(width * usize::from(n));
+            return Ok(());
+        }
+        self.write_text(&vec![c; usize::from(n)])?;
+        Ok(())
+    }
+
+    /// Writes `text` on the `bg` background, extending the background to the end of the line,
+    /// then resets the terminal. This is how a highlighted line, such as a selected menu item,
+    /// is drawn.
+    ///
+    /// `text` should start at the beginning of the line and fit on it. On terminals with `bce`
+    /// the rest of the line is erased with `el`; otherwise it's filled with spaces up to the
+    /// width given by `dims`.
+    pub fn fill_line_bg(&mut self, text: &str, bg: color::Color) -> Result<()> {
+        self.bg(bg)?;
+        self.write_text(text.as_bytes())?;
+        if self.back_color_erase() && self.ti.strings.contains_key("el") {
+            self.write_cap("el", &[])?;
+        } else {
+            let (columns, _) = self.dims();
+            let width = crate::display_width(text.as_bytes());
+            let padding = (columns as usize).saturating_sub(width);
+            self.repeat_char(b' ', u16::try_from(padding).unwrap_or(u16::MAX))?;
+        }
+        self.reset()
+    }
+
+    /// Moves the cursor up `n` lines, using the `cuu` capability when the terminal has it and
+    /// repeating `cuu1` otherwise.
+    pub fn cursor_up_n(&mut self, n: u16) -> Result<()> {
+        self.move_cursor("cuu", "cuu1", n)
+    }
+
+    /// Moves the cursor down `n` lines, using `cud` or repeating `cud1`.
+    ///
+    /// On many terminals `cud1` is a newline, which scrolls at the bottom of the screen.
+    pub fn cursor_down_n(&mut self, n: u16) -> Result<()> {
+        self.move_cursor("cud", "cud1", n)
+    }
+
+    /// Moves the cursor left `n` columns, using `cub` or repeating `cub1`.
+    pub fn cursor_left_n(&mut self, n: u16) -> Result<()> {
+        self.move_cursor("cub", "cub1", n)
+    }
+
+    /// Moves the cursor right `n` columns, using `cuf` or repeating `cuf1`.
+    pub fn cursor_right_n(&mut self, n: u16) -> Result<()> {
+        self.move_cursor("cuf", "cuf1", n)
+    }
+
+    /// Moves the cursor to the start of the line `n` lines down.
+    ///
+    /// A single line uses the `nel` capability, falling back to `cr` followed by `cud1`. More
+    /// lines use `CSI n E` on ANSI terminals, and `cr` followed by `cursor_down_n` otherwise.
+    pub fn next_line(&mut self, n: u16) -> Result<()> {
+        if n == 0 {
+            return Ok(());
+        }
+        if n == 1 && self.ti.strings.contains_key("nel") {
+            self.write_cap("nel", &[])?;
+        } else if n > 1 && self.is_ansi() {
+            write!(self.out, "\x1B[{}E", n)?;
+        } else {
+            self.carriage_return()?;
+            self.cursor_down_n(n)?;
+        }
+        self.column.line_start();
+        Ok(())
+    }
+
+    /// Moves the cursor to the start of the line `n` lines up.
+    ///
+    /// This uses `CSI n F` on ANSI terminals, and `cr` followed by `cursor_up_n` otherwise.
+    pub fn prev_line(&mut self, n: u16) -> Result<()> {
+        if n == 0 {
+            return Ok(());
+        }
+        if self.is_ansi() {
+            write!(self.out, "\x1B[{}F", n)?;
+        } else {
+            self.carriage_return()?;
+            self.cursor_up_n(n)?;
+        }
+        self.column.line_start();
+        Ok(())
+    }
+
+    fn move_cursor(&mut self, cap: &str, step: &str, n: u16) -> Result<()> {
+        // Terminals move by one when asked to move by zero, so don't ask.
+        if n == 0 {
+            return Ok(());
+        }
+        if n > 1 && self.ti.strings.contains_key(cap) {
+            return self.write_cap(cap, &[Param::Number(i32::from(n))]);
+        }
+        if !self.ti.strings.contains_key(step) {
+            self.report_unsupported(step);
+            return Err(crate::Error::NotSupported);
+        }
+        let mut code = Vec::new();
+        for _ in 0..n {
+            self.apply_cap(step, &[], &mut code)?;
+        }
+        self.out.write_all(&code)?;
+        Ok(())
+    }
+
+    /// Returns the terminal to a sane state, like `tput reset`.
+    ///
+    /// Writes the reset strings `rs1`, `rs2` and `rs3`, or the initialization strings `is1`,
+    /// `is2` and `is3` if the terminal has no reset strings. This undoes modes such as
+    /// application keypad or the alternate character set that a crashed program may have left
+    /// enabled.
+    pub fn full_reset(&mut self) -> Result<()> {
+        for caps in [["rs1", "rs2", "rs3"], ["is1", "is2", "is3"]] {
+            if !caps.iter().any(|cap| self.ti.strings.contains_key(cap)) {
+                continue;
+            }
+            let mut code = Vec::new();
+            for cap in caps {
+                if self.ti.strings.contains_key(cap) {
+                    self.apply_cap(cap, &[], &mut code)?;
+                }
+            }
+            self.out.write_all(&code)?;
+            self.style = None;
+            return Ok(());
+        }
+        Err(crate::Error::NotSupported)
+    }
+
+    /// Resets the terminal to its initial state with RIS (`ESC c`).
+    ///
+    /// This is the last resort when `full_reset` isn't enough. It's disruptive: besides
+    /// resetting all modes, it clears the screen, and on some terminals the scrollback too.
+    pub fn hard_reset(&mut self) -> Result<()> {
+        if !self.is_ansi() {
+            return Err(crate::Error::NotSupported);
+        }
+        self.out.write_all(b"\x1Bc")?;
+        self.style = None;
+        Ok(())
+    }
+
+    /// Enables the kitty keyboard protocol with the given `keyboard` flags, saving the current
+    /// flags on the terminal's stack.
+    ///
+    /// Terminals that don't implement the protocol ignore this, and keep sending legacy key
+    /// codes. Call `pop_keyboard_enhancement` to restore the previous flags before exiting.
+    pub fn push_keyboard_enhancement(&mut self, flags: u8) -> Result<()> {
+        if !self.is_ansi() {
+            return Err(crate::Error::NotSupported);
+        }
+        write!(self.out, "\x1B[>{}u", flags)?;
+        Ok(())
+    }
+
+    /// Restores the keyboard protocol flags saved by `push_keyboard_enhancement`.
+    pub fn pop_keyboard_enhancement(&mut self) -> Result<()> {
+        if !self.is_ansi() {
+            return Err(crate::Error::NotSupported);
+        }
+        self.out.write_all(b"\x1B[<u")?;
+        Ok(())
+    }
+
+    /// Sets the shape of the cursor.
+    ///
+    /// Uses the (extended) `Ss` capability if present, and the DECSCUSR sequence on other ANSI
+    /// terminals.
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) -> Result<()> {
+        let n = shape as i32;
+        if self.ti.strings.contains_key("Ss") {
+            return self.write_cap("Ss", &[Param::Number(n)]);
+        }
+        if !self.is_ansi() {
+            return Err(crate::Error::NotSupported);
+        }
+        write!(self.out, "\x1B[{} q", n)?;
+        Ok(())
+    }
+
+    /// Returns true if lines should be drawn with Unicode box-drawing characters rather than the
+    /// terminal's alternate character set.
+    ///
+    /// That's the case when the terminfo entry has no `acsc` capability but the locale (from
+    /// `LC_ALL`, `LC_CTYPE` or `LANG`, see `set_environment`) uses UTF-8, as with many modern
+    /// terminals.
+    pub fn prefers_unicode_lines(&self) -> bool {
+        if self.ti.strings.contains_key("acsc") {
+            return false;
+        }
+        let environment = &self.environment;
+        let locale = [
+            &environment.lc_all,
+            &environment.lc_ctype,
+            &environment.lang,
+        ]
+        .into_iter()
+        .flatten()
+        .find(|value| !value.is_empty())
+        .cloned()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+        locale.contains("utf-8") || locale.contains("utf8")
+    }
+
+    /// Returns the character to write to draw `c`.
+    ///
+    /// If the terminal has an alternate character set (`acsc`), this is the character that
+    /// draws `c` in that set, and must be written between `enable_line_drawing` and
+    /// `disable_line_drawing`. Otherwise, it's the Unicode box-drawing character if
+    /// `prefers_unicode_lines`, and an ASCII approximation such as `+` or `-` if not.
+    pub fn line_char(&self, c: LineChar) -> char {
+        let (code, unicode, ascii) = c.chars();
+        if let Some(acsc) = self.ti.strings.get("acsc") {
+            // `acsc` pairs each VT100 code with the character the terminal uses for it.
+            return acsc
+                .chunks_exact(2)
+                .find(|pair| pair[0] == code)
+                .map_or(ascii, |pair| char::from(pair[1]));
+        }
+        if self.prefers_unicode_lines() {
+            unicode
+        } else {
+            ascii
+        }
+    }
+
+    /// Switches to the alternate character set (`smacs`) so that the characters returned by
+    /// `line_char` draw lines.
+    ///
+    /// Does nothing if the terminal has no alternate character set, as `line_char` then returns
+    /// characters that draw lines by themselves. The first call also calls `init_charset`.
+    pub fn enable_line_drawing(&mut self) -> Result<()> {
+        if !self.ti.strings.contains_key("acsc") {
+            return Ok(());
+        }
+        if !self.charset_initialized {
+            self.init_charset()?;
+        }
+        self.write_cap("smacs", &[])
+    }
+
+    /// Enables the alternate character set (`enacs`), which some terminals require before
+    /// `smacs` works.
+    ///
+    /// Returns `Ok(false)` without writing anything if the terminal doesn't have `enacs`, as most
+    /// don't need it. `enable_line_drawing` calls this the first time it's used.
+    pub fn init_charset(&mut self) -> Result<bool> {
+        self.charset_initialized = true;
+        if !self.ti.strings.contains_key("enacs") {
+            return Ok(false);
+        }
+        self.write_cap("enacs", &[])?;
+        Ok(true)
+    }
+
+    /// Switches back from the alternate character set (`rmacs`), see `enable_line_drawing`.
+    pub fn disable_line_drawing(&mut self) -> Result<()> {
+        if !self.ti.strings.contains_key("acsc") {
+            return Ok(());
+        }
+        self.write_cap("rmacs", &[])
+    }
+
+    /// Restores the terminal's default cursor shape.
+    ///
+    /// Uses the (extended) `Se` capability if present, and the DECSCUSR sequence on other ANSI
+    /// terminals.
+    pub fn reset_cursor_shape(&mut self) -> Result<()> {
+        if self.ti.strings.contains_key("Se") {
+            return self.write_cap("Se", &[]);
+        }
+        if !self.is_ansi() {
+            return Err(crate::Error::NotSupported);
+        }
+        self.out.write_all(b"\x1B[0 q")?;
+        Ok(())
+    }
+
+    /// Puts the keypad into application mode (`smkx`), as full-screen programs that read cursor
+    /// keys should.
+    ///
+    /// The sequences returned by `key_sequence` are only valid in this mode.
+    pub fn keypad_xmit(&mut self) -> Result<()> {
+        self.write_cap("smkx", &[])
+    }
+
+    /// Takes the keypad out of application mode (`rmkx`).
+    pub fn keypad_local(&mut self) -> Result<()> {
+        self.write_cap("rmkx", &[])
+    }
+
+    /// Returns the sequence the terminal sends when `key` is pressed, if known.
+    ///
+    /// Most terminals only send these sequences in application keypad mode (see `keypad_xmit`).
+    /// Keys without a capability of their own, such as `Key::Char`, yield `None`.
+    pub fn key_sequence(&self, key: Key) -> Option<&[u8]> {
+        let fkey;
+        let cap = match key {
+            Key::Char(_) | Key::Ctrl(_) | Key::Alt(_) | Key::Enter | Key::Tab | Key::Escape => {
+                return None
+            }
+            Key::Up => "kcuu1",
+            Key::Down => "kcud1",
+            Key::Left => "kcub1",
+            Key::Right => "kcuf1",
+            Key::Home => "khome",
+            Key::End => "kend",
+            Key::PageUp => "kpp",
+            Key::PageDown => "knp",
+            Key::Insert => "kich1",
+            Key::Delete => "kdch1",
+            Key::Backspace => "kbs",
+            Key::BackTab => "kcbt",
+            Key::F(n) => {
+                fkey = format!("kf{}", n);
+                &fkey
+            }
+        };
+        self.ti
+            .strings
+            .get(cap)
+            .map(|s| &s[..])
+            .filter(|s| !s.is_empty())
+    }
+
+    /// Reads a key press from `input`, returning `Ok(None)` on end of input or timeout.
+    ///
+    /// `input` must be connected to the same terminal, which should be in raw mode so keys are
+    /// delivered as they are pressed. Special keys are recognized using the terminfo entry's key
+    /// capabilities (see `key_sequence`), as well as the sequences commonly sent outside of
+    /// application keypad mode. Escape sequences that aren't recognized are skipped.
+    ///
+    /// The Escape key sends the same byte that starts escape sequences, so it's only reported
+    /// once reading the next byte times out (or the input ends). `input` should therefore time out
+    /// after a short while, e.g. using `VMIN`/`VTIME`; otherwise Escape is only reported along
+    /// with the next key, as `Alt` and that key.
+    pub fn read_key<R: Read>(&self, input: &mut R) -> Result<Option<Key>> {
+        loop {
+            let b = match read_reply_byte(input)? {
+                Some(b) => b,
+                None => return Ok(None),
+            };
+            let key = match b {
+                0x1B => match self.read_escape_sequence(input)? {
+                    Some(key) => key,
+                    None => continue,
+                },
+                b'\r' | b'\n' => Key::Enter,
+                b'\t' => Key::Tab,
+                0x08 | 0x7F => Key::Backspace,
+                0x00 => Key::Ctrl(' '),
+                0x01..=0x1A => Key::Ctrl((b'a' + b - 1) as char),
+                0x1C..=0x1F => Key::Ctrl((b + 0x40) as char),
+                _ => match read_utf8_char(b, input)? {
+                    Some(c) => Key::Char(c),
+                    None => continue,
+                },
+            };
+            return Ok(Some(key));
+        }
+    }
+
+    /// Reads the rest of an escape sequence after the initial ESC, returning `None` if it isn't
+    /// recognized.
+    fn read_escape_sequence<R: Read>(&self, input: &mut R) -> Result<Option<Key>> {
+        let known = self.key_sequences.get_or_init(|| {
+            SPECIAL_KEYS
+                .iter()
+                .copied()
+                .chain((0..64).map(Key::F))
+                .filter_map(|key| Some((self.key_sequence(key)?.to_vec(), key)))
+                .chain(
+                    FALLBACK_KEY_SEQUENCES
+                        .iter()
+                        .map(|&(seq, key)| (seq.to_vec(), key)),
+                )
+                .collect()
+        });
+
+        // Read for as long as the sequence could still become a known one.
+        let mut seq = vec![0x1B];
+        loop {
+            if let Some(&(_, key)) = known.iter().find(|(s, _)| *s == seq) {
+                return Ok(Some(key));
+            }
+            if seq.len() >= MAX_KEY_SEQUENCE_LEN || !known.iter().any(|(s, _)| s.starts_with(&seq))
+            {
+                break;
+            }
+            match read_reply_byte(input)? {
+                Some(b) => seq.push(b),
+                None => break,
+            }
+        }
+
+        match seq[..] {
+            [_] => Ok(Some(Key::Escape)),
+            [_, b] if b == b' ' || b.is_ascii_graphic() => Ok(Some(Key::Alt(b as char))),
+            [_, 0x1B] => Ok(Some(Key::Alt('\x1B'))),
+            [_, b] if b >= 0x80 => Ok(read_utf8_char(b, input)?.map(Key::Alt)),
+            _ => {
+                // Skip the rest of an unknown control sequence, up to its final byte.
+                if seq[1] == b'[' {
+                    while seq.len() < MAX_KEY_SEQUENCE_LEN
+                        && !(seq.len() > 2 && (0x40..=0x7E).contains(&seq[seq.len() - 1]))
+                    {
+                        match read_reply_byte(input)? {
+                            Some(b) => seq.push(b),
+                            None => break,
+                        }
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Create a terminal writing to `out` that shares this terminal's terminfo entry and settings.
+    pub fn with_output<U: Write>(&self, out: U) -> TerminfoTerminal<U> {
+        TerminfoTerminal {
+            out: Output::new(out, self.out.capacity),
+            ti: self.ti.clone(),
+            num_colors: self.num_colors,
+            truecolor: self.truecolor,
+            column: self.column.clone(),
+            unsupported_callback: self.unsupported_callback.clone(),
+            style: None,
+            attr_fallbacks: self.attr_fallbacks,
+            bright_via_bold: self.bright_via_bold,
+            bold: self.bold,
+            auto_flush_attrs: self.auto_flush_attrs,
+            charset_initialized: self.charset_initialized,
+            tmux_passthrough: self.tmux_passthrough,
+            baud_rate: self.baud_rate,
+            key_sequences: self.key_sequences.clone(),
+            environment: self.environment.clone(),
+        }
+    }
+
+    /// Replaces the environment variables the terminal consults, such as `COLUMNS` and `LINES`
+    /// for `dims` and `TMUX` and `STY` for `multiplexer`.
+    ///
+    /// These are read from the process when the terminal is created; only the ones a
+    /// `TerminfoTerminal` consults itself are used, not those that locate its terminfo entry.
+    pub fn set_environment(&mut self, environment: Environment) {
+        self.environment = environment;
+    }
+
+    /// Returns the environment variables the terminal consults, see `set_environment`.
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// Returns the terminfo entry this terminal uses.
+    pub fn terminfo(&self) -> &Arc<TermInfo> {
+        &self.ti
+    }
+
+    /// Returns the number of colors `fg` and `bg` accept.
+    pub fn num_colors(&self) -> u32 {
+        self.num_colors
+    }
+
+    /// Overrides the number of colors the terminal supports.
+    ///
+    /// Use this when the terminfo entry understates the terminal's capabilities. Colors beyond
+    /// those declared by the entry are set with the xterm 256-color sequences.
+    pub fn set_num_colors(&mut self, num_colors: u32) {
+        self.num_colors = num_colors;
+    }
+
+    /// Sets colors with the standard ANSI sequences, supporting `num_colors` of them, whatever the
+    /// terminfo entry says.
+    ///
+    /// Use this when the entry lacks colors (or describes them wrongly) but the terminal is known
+    /// to understand ANSI colors. This replaces the entry's `setaf`, `setab` and `colors` for this
+    /// terminal (and those created from it with `with_output`), and adds `op` so that `reset`
+    /// works if the entry has no way to reset the colors.
+    pub fn set_ansi_colors(&mut self, num_colors: u32) {
+        let ti = Arc::make_mut(&mut self.ti);
+        ti.strings.insert("setaf", ANSI_SETAF.to_vec());
+        ti.strings.insert("setab", ANSI_SETAB.to_vec());
+        if !["sgr0", "sgr", "op"]
+            .iter()
+            .any(|&cap| ti.strings.contains_key(cap))
+        {
+            ti.strings.insert("op", ANSI_OP.to_vec());
+        }
+        ti.numbers.insert("colors", num_colors);
+        self.num_colors = num_colors;
+    }
+
+    /// Enables or disables flushing the output after each change of colors or attributes.
+    ///
+    /// With a line-buffered writer such as `Stdout`, a change made by `fg`, `bg`, `set_colors`,
+    /// `attr` or `reset` otherwise only becomes visible once a newline is written. Enable this for
+    /// interactive prompts and the like, where that lag shows; it costs a write per change.
+    /// Disabled by default.
+    pub fn set_auto_flush_attrs(&mut self, enabled: bool) {
+        self.auto_flush_attrs = enabled;
+    }
+
+    /// Flushes the output if `set_auto_flush_attrs` is enabled.
+    fn auto_flush(&mut self) -> Result<()> {
+        if self.auto_flush_attrs {
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Sets the foreground color to the 256-color palette entry `index`.
+    ///
+    /// Unlike `fg`, this always uses `index` as is, even when `set_bright_via_bold` is enabled.
+    /// Returns `Err(Error::ColorOutOfRange)` if the terminal has fewer than 256 colors.
+    pub fn fg_256(&mut self, index: u8) -> Result<()> {
+        self.set_color_256(true, index)
+    }
+
+    /// Sets the background color to the 256-color palette entry `index`, see `fg_256`.
+    pub fn bg_256(&mut self, index: u8) -> Result<()> {
+        self.set_color_256(false, index)
+    }
+
+    fn set_color_256(&mut self, foreground: bool, index: u8) -> Result<()> {
+        if self.num_colors < 256 {
+            return Err(crate::Error::ColorOutOfRange);
+        }
+        let color = color::Color::from(index);
+        let code = self.raw_color_code(foreground, color)?;
+        self.out.write_all(&code)?;
+        // `fg` sets some colors differently, so don't let it assume this one is in effect.
+        let known = !(foreground && self.bold_for_bright(color));
+        if let Some(style) = &mut self.style {
+            let current = if foreground {
+                &mut style.fg
+            } else {
+                &mut style.bg
+            };
+            *current = Some(color).filter(|_| known);
+        }
+        Ok(())
+    }
+
+    /// Holds on to output until `bytes` bytes are pending, or `present` (or `flush`) is called.
+    ///
+    /// This coalesces the many small writes made while drawing a frame: call `present` once the
+    /// frame is drawn. Methods that read a reply from the terminal, such as `query_background`,
+    /// write out the pending output first. `get_ref` and `get_mut` only give access to what has
+    /// already been written out. The default, 0, writes everything immediately.
+    pub fn set_buffer_capacity(&mut self, bytes: usize) {
+        self.out.capacity = bytes;
+    }
+
+    /// Writes out the pending output (see `set_buffer_capacity`) and flushes the writer.
+    pub fn present(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Enables or disables substitutes for unsupported attributes in `attr`.
+    ///
+    /// When enabled, italics fall back to underline, standout to reverse video plus bold (or just
+    /// reverse video if bold isn't available), and dim and blink are dropped, instead of returning
+    /// `Err(Error::NotSupported)`. Turning standout off without `rmso` still fails; use `reset`.
+    /// `supports_attr` still reports what the terminal itself supports. Disabled by default.
+    pub fn set_attr_fallbacks(&mut self, enabled: bool) {
+        self.attr_fallbacks = enabled;
+    }
+
+    /// Enables or disables setting the bright foreground colors (8 to 15) as their normal
+    /// counterparts plus bold.
+    ///
+    /// Many terminals show bold text in the bright version of its color, and themes written for
+    /// them use bold to get bright colors. When enabled, `fg(BRIGHT_RED)` writes bold and
+    /// `setaf 1` rather than `setaf 9`, reproducing those themes faithfully. Setting a normal
+    /// color afterwards turns bold off again as `attr(Attr::Bold(false))` does, unless bold was
+    /// also requested with `attr`. Terminals that can't turn bold off alone are reset instead,
+    /// setting the background and attributes again if they're known from an earlier `reset`.
+    /// Background colors are unaffected. Disabled by default.
+    pub fn set_bright_via_bold(&mut self, enabled: bool) {
+        self.bright_via_bold = enabled;
+    }
+
+    /// Enables or disables wrapping OSC sequences in tmux's passthrough sequence.
+    ///
+    /// tmux swallows the OSC sequences it doesn't understand itself, such as those used by
+    /// `copy_to_clipboard`, `set_default_foreground` and (before tmux 3.4) `hyperlink`. When
+    /// enabled and `multiplexer` detects tmux, these are wrapped so tmux forwards them to the
+    /// outer terminal. This requires `set -g allow-passthrough on` in the tmux configuration.
+    /// Disabled by default.
+    pub fn set_tmux_passthrough(&mut self, enabled: bool) {
+        self.tmux_passthrough = enabled;
+    }
+
+    /// Sets the speed of the line to the terminal in bits per second, so that capabilities are
+    /// padded for it as described in `TermInfo::apply_cap_with_padding`, or `None` to remove
+    /// padding specifications.
+    ///
+    /// Only terminals on a slow serial line need padding. `None` by default.
+    pub fn set_baud_rate(&mut self, baud_rate: Option<u32>) {
+        self.baud_rate = baud_rate;
+    }
+
+    /// Sets a callback to be called with the name of a capability whenever an operation fails
+    /// with `Error::NotSupported` because the terminal lacks that capability.
+    ///
+    /// This is meant for diagnostics, e.g. logging which capabilities a user's terminal is
+    /// missing. The callback is called every time, so deduplicate the names if needed. Clones of
+    /// the terminal (including those from `with_output`) share the callback.
+    pub fn set_unsupported_callback(&mut self, callback: Box<dyn FnMut(&str) + Send>) {
+        self.unsupported_callback = Some(Arc::new(Mutex::new(callback)));
+    }
+
+    /// Forgets the colors and attributes the terminal is known to be set to.
+    ///
+    /// After a `reset`, the terminal keeps track of the colors and attributes it sets, and `fg`,
+    /// `bg`, `set_colors` and `attr` skip writing anything when the terminal is already set that
+    /// way. Escape sequences written as text (through `Write`) are noticed, but those written to
+    /// the underlying writer directly aren't: call this afterwards so that the next changes are
+    /// written in full. Tracking resumes at the next `reset`.
+    pub fn force_style_resync(&mut self) {
+        self.style = None;
+    }
+
+    fn report_unsupported(&self, cap: &str) {
+        if let Some(callback) = &self.unsupported_callback {
+            let mut callback = callback.lock().unwrap_or_else(PoisonError::into_inner);
+            callback(cap);
+        }
+    }
+
+    /// Expands the capability `cap` with `params` into `out`, with padding if `set_baud_rate`
+    /// asked for it.
+    fn apply_cap(&self, cap: &str, params: &[Param], out: &mut dyn io::Write) -> Result<()> {
+        match self.baud_rate {
+            Some(baud_rate) => self.ti.apply_cap_with_padding(cap, params, baud_rate, out),
+            None => self.ti.apply_cap(cap, params, out),
+        }
+    }
+
+    /// Writes the capability `cap` with `params`, reporting it if it's missing.
+    fn write_cap(&mut self, cap: &str, params: &[Param]) -> Result<()> {
+        let result = match self.baud_rate {
+            Some(baud_rate) => {
+                self.ti
+                    .apply_cap_with_padding(cap, params, baud_rate, &mut self.out)
+            }
+            None => self.ti.apply_cap(cap, params, &mut self.out),
+        };
+        if let Err(crate::Error::NotSupported) = result {
+            self.report_unsupported(cap);
+        }
+        result
+    }
+
+    /// Returns the maximum number of color pairs the terminal can display at once (the `pairs`
+    /// capability), or 0 if unknown.
+    ///
+    /// This only matters on terminals that set colors by pair; `fg`, `bg` and `set_colors` set
+    /// the foreground and background independently and don't consult it.
+    pub fn max_color_pairs(&self) -> u32 {
+        self.ti.max_pairs().unwrap_or(0)
+    }
+
+    /// Returns the terminal's size as `(columns, lines)`.
+    ///
+    /// Like ncurses, this prefers the `COLUMNS` and `LINES` environment variables (see
+    /// `set_environment`), then the `cols` and `lines` capabilities, and finally assumes 80x24.
+    /// Values that aren't positive numbers are skipped. This does not query the size of the
+    /// terminal window.
+    pub fn dims(&self) -> (u32, u32) {
+        let dim = |var: &Option<String>, cap, default| {
+            let positive = |n: &u32| *n > 0;
+            var.as_deref()
+                .and_then(|v| v.trim().parse().ok())
+                .filter(positive)
+                .or_else(|| self.ti.numbers.get(cap).copied().filter(positive))
+                .unwrap_or(default)
+        };
+        (
+            dim(&self.environment.columns, "cols", 80),
+            dim(&self.environment.lines, "lines", 24),
+        )
+    }
+
+    /// Returns the terminal emulator named by the `TERM_PROGRAM` environment variable (see
+    /// `set_environment`), which several emulators set to identify themselves.
+    ///
+    /// Use this to enable features known to work in a specific emulator that its terminfo entry
+    /// (often plain `xterm-256color`) doesn't describe.
+    pub fn term_program(&self) -> Option<TermProgram> {
+        self.environment
+            .term_program
+            .as_deref()
+            .filter(|name| !name.is_empty())
+            .map(TermProgram::from_name)
+    }
+
+    /// Returns the version of the terminal emulator, from the `TERM_PROGRAM_VERSION` environment
+    /// variable, see `term_program`.
+    ///
+    /// Compare it with another `Version`, e.g. `Version::parse("3.4")`, to check for features.
+    pub fn term_program_version(&self) -> Option<Version> {
+        Version::parse(self.environment.term_program_version.as_deref()?)
+    }
+
+    /// Returns the terminal multiplexer the program is running in, if any.
+    ///
+    /// This checks the `TMUX` and `STY` environment variables (see `set_environment`), which
+    /// tmux and screen set for the programs they run, then the terminal's name. As tmux usually
+    /// identifies itself as `screen`, a `screen` name without `STY` may also mean tmux.
+    pub fn multiplexer(&self) -> Option<Multiplexer> {
+        let set = |var: &Option<String>| var.as_deref().is_some_and(|v| !v.is_empty());
+        if set(&self.environment.tmux) {
+            return Some(Multiplexer::Tmux);
+        }
+        if set(&self.environment.sty) {
+            return Some(Multiplexer::Screen);
+        }
+        let name = self.ti.names.first()?;
+        if name.starts_with("tmux") {
+            Some(Multiplexer::Tmux)
+        } else if name.starts_with("screen") {
+            Some(Multiplexer::Screen)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if erasing fills the cleared area with the current background color (the `bce`
+    /// capability).
+    ///
+    /// Only set a background color before erasing (e.g. with `delete_line`) when this is true;
+    /// otherwise the cleared area uses the default background regardless.
+    pub fn back_color_erase(&self) -> bool {
+        self.ti.back_color_erase()
+    }
+
+    /// Returns true if the terminal has automatic margins (the `am` capability).
+    ///
+    /// Such terminals wrap to the next line when text is written past the last column. Terminals
+    /// without automatic margins keep overwriting the last column instead.
+    pub fn auto_margins(&self) -> bool {
+        self.ti.auto_margins()
+    }
+
+    fn is_ansi(&self) -> bool {
+        self.ti.names.first().is_some_and(|name| is_ansi(name))
+    }
+
+    fn sync_update(&mut self, begin: bool) -> Result<()> {
+        // The (extended) Sync capability takes 1 to begin and 2 to end an update.
+        if self.ti.strings.contains_key("Sync") {
+            let param = if begin { 1 } else { 2 };
+            return self.write_cap("Sync", &[Param::Number(param)]);
+        }
+        // Otherwise use the DEC private mode directly; ANSI terminals ignore unknown modes.
+        if self.is_ansi() {
+            let cmd: &[u8] = if begin {
+                b"\x1B[?2026h"
+            } else {
+                b"\x1B[?2026l"
+            };
+            self.out.write_all(cmd)?;
+            return Ok(());
+        }
+        Err(crate::Error::NotSupported)
+    }
+
+    /// Returns the code that sets the foreground (or background) to `color`.
+    ///
+    /// Prefers `setaf`/`setab`, falling back to the legacy `setf`/`setb` capabilities, which
+    /// number the colors differently. Colors beyond what the terminfo entry declares (see
+    /// `set_num_colors`) use the xterm 256-color sequences.
+    fn color_code(&self, foreground: bool, color: color::Color) -> Result<Vec<u8>> {
+        let mut code = Vec::new();
+        let color = if foreground && self.bold_for_bright(color) {
+            self.apply_cap("bold", &[], &mut code)?;
+            color - 8
+        } else {
+            self.dim_if_necessary(color)
+        };
+        code.extend(self.raw_color_code(foreground, color)?);
+        Ok(code)
+    }
+
+    /// Returns the code for setting `color` without substituting another color for it.
+    fn raw_color_code(&self, foreground: bool, color: color::Color) -> Result<Vec<u8>> {
+        let mut code = Vec::new();
+        if self.num_colors <= color {
+            return Err(crate::Error::ColorOutOfRange);
+        }
+        if !self.can_set_color(foreground) {
+            self.report_unsupported(if foreground { "setaf" } else { "setab" });
+            return Err(crate::Error::NotSupported);
+        }
+        if color >= self.ti.colors() {
+            let sgr = if foreground { 38 } else { 48 };
+            write!(code, "\x1B[{};5;{}m", sgr, color)?;
+            return Ok(code);
+        }
+        let (ansi, legacy) = if foreground {
+            ("setaf", "setf")
+        } else {
+            ("setab", "setb")
+        };
+        if self.ti.strings.contains_key(ansi) {
+            self.apply_cap(ansi, &[Param::Number(color as i32)], &mut code)?;
+        } else {
+            let color = ansi_to_legacy_color(color);
+            self.apply_cap(legacy, &[Param::Number(color as i32)], &mut code)?;
+        }
+        Ok(code)
+    }
+
+    fn can_set_color(&self, foreground: bool) -> bool {
+        let caps = if foreground {
+            ["setaf", "setf"]
+        } else {
+            ["setab", "setb"]
+        };
+        caps.iter().any(|&cap| self.ti.strings.contains_key(cap))
+    }
+
+    /// Returns true if the foreground color `color` is set as its normal counterpart plus bold,
+    /// see `set_bright_via_bold`.
+    fn bold_for_bright(&self, color: color::Color) -> bool {
+        self.bright_via_bold && (8..16).contains(&color) && self.ti.strings.contains_key("bold")
+    }
+
+    /// Returns true if the style cache knows `attr` is already set.
+    fn has_style_attr(&self, attr: Attr) -> bool {
+        self.style.as_ref().is_some_and(|s| s.has_attr(attr))
+    }
+
+    /// Turns `attr` off with `sgr`, setting the other attributes and the colors known from the
+    /// last `reset` again. Attributes set before the first `reset` are turned off too.
+    fn clear_attr_with_sgr(&mut self, attr: Attr) -> Result<()> {
+        let mut style = self.style.clone().unwrap_or_default();
+        style.set_attr(attr);
+        if attr != Attr::Protected(false) && style.fg.is_some_and(|c| self.bold_for_bright(c)) {
+            // The bright color is now shown as its normal counterpart.
+            style.fg = None;
+        }
+        let on = |attr| Param::Number(i32::from(style.attrs.contains(&attr)));
+        let params = [
+            on(Attr::Standout(true)),
+            on(Attr::Underline(true)),
+            on(Attr::Reverse),
+            on(Attr::Blink),
+            on(Attr::Dim(true)),
+            on(Attr::Bold(true)),
+            on(Attr::Secure),
+            on(Attr::Protected(true)),
+            Param::Number(0),
+        ];
+        self.write_cap("sgr", &params)?;
+        // `sgr` has no parameter for italics, and resets the colors.
+        if style.attrs.contains(&Attr::Italic(true)) {
+            self.write_cap("sitm", &[])?;
+        }
+        let mut code = Vec::new();
+        if let Some(fg) = style.fg {
+            code.extend(self.color_code(true, fg)?);
+        }
+        if let Some(bg) = style.bg {
+            code.extend(self.color_code(false, bg)?);
+        }
+        self.out.write_all(&code)?;
+        if attr != Attr::Protected(false) {
+            self.bold = Bold::Off;
+        }
+        if self.style.is_some() {
+            self.style = Some(style);
+        }
+        self.auto_flush()
+    }
+
+    /// Turns off bold if it's only on because of a bright foreground color, keeping the other
+    /// attributes.
+    fn end_bold_for_bright(&mut self) -> Result<()> {
+        if self.bold != Bold::ForBright {
+            return Ok(());
+        }
+        if self.supports_attr(Attr::Bold(false)) {
+            return self.attr(Attr::Bold(false));
+        }
+        if !self.ti.strings.contains_key("sgr0") {
+            // Nothing turns bold off.
+            return Ok(());
+        }
+        // Reset everything, then set the background and the known attributes again.
+        let style = self.style.take();
+        self.reset()?;
+        if let Some(style) = style {
+            for &attr in &style.attrs {
+                if attr != Attr::Bold(true) {
+                    self.attr(attr)?;
+                }
+            }
+            if let Some(bg) = style.bg {
+                self.bg(bg)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn dim_if_necessary(&self, color: color::Color) -> color::Color {
+        if color >= self.num_colors && (8..16).contains(&color) {
+            color - 8
+        } else {
+            color
+        }
+    }
+}
+
+impl TerminfoTerminal<File> {
+    /// Create a second handle to the same terminal, sharing the parsed terminfo entry.
+    ///
+    /// See `File::try_clone`; use `with_output` for other kinds of writers.
+    pub fn try_clone(&self) -> io::Result<TerminfoTerminal<File>> {
+        Ok(self.with_output(self.out.get_ref().try_clone()?))
+    }
+}
+
+impl TerminfoTerminal<Vec<u8>> {
+    /// Takes the text and escape sequences written so far, leaving the buffer empty.
+    ///
+    /// Expanding capabilities doesn't do any I/O, so code that can't block (e.g. async code) can
+    /// draw to a terminal writing to a `Vec<u8>` (see `with_output`) and write the returned bytes
+    /// to the real terminal itself.
+    pub fn take_buffer(&mut self) -> Vec<u8> {
+        let _ = self.out.write_pending();
+        mem::take(self.out.get_mut())
+    }
+}
+
+impl<T: Write> Write for TerminfoTerminal<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        let written = &buf[..n];
+        // Escape sequences written as text may change the style behind our back.
+        if written.contains(&b'\x1B') {
+            self.style = None;
+        }
+        self.column.track(written, usize::from(self.tab_width()));
+        Ok(n)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -14,10 +14,12 @@ use self::Param::*;
 use self::States::*;
 
 use std::iter::repeat;
+use std::time::Duration;
 
 #[derive(Clone, Copy, PartialEq)]
 enum States {
     Nothing,
+    Dollar,
     Delay,
     Percent,
     SetVar,
@@ -86,9 +88,9 @@ impl ::std::fmt::Display for Error {
         match self {
             StackUnderflow => f.write_str("not enough elements on the stack"),
             TypeMismatch => f.write_str("type mismatch"),
-            UnrecognizedFormatOption(_) => f.write_str("unrecognized format option"),
-            InvalidVariableName(_) => f.write_str("invalid variable name"),
-            InvalidParameterIndex(_) => f.write_str("invalid parameter index"),
+            UnrecognizedFormatOption(c) => write!(f, "unrecognized format option {:?}", c),
+            InvalidVariableName(c) => write!(f, "invalid variable name {:?}", c),
+            InvalidParameterIndex(c) => write!(f, "invalid parameter index {:?}", c),
             MalformedCharacterConstant => f.write_str("malformed character constant"),
             IntegerConstantOverflow => f.write_str("integer constant computation overflowed"),
             MalformedIntegerConstant => f.write_str("malformed integer constant"),
@@ -118,6 +120,64 @@ impl Variables {
     }
 }
 
+/// A padding specification in a capability, such as `$<5>` or `$<10*/>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delay {
+    /// In tenths of a millisecond
+    tenths: u32,
+    proportional: bool,
+    mandatory: bool,
+}
+
+impl Delay {
+    /// Parses the part between `$<` and `>`: milliseconds with at most one decimal, then `*`
+    /// and/or `/`.
+    fn parse(spec: &[u8]) -> Option<Delay> {
+        let digits = spec.iter().take_while(|c| c.is_ascii_digit()).count();
+        let (whole, mut rest) = spec.split_at(digits);
+        let mut tenths = std::str::from_utf8(whole)
+            .ok()?
+            .parse::<u32>()
+            .ok()?
+            .checked_mul(10)?;
+        if let [b'.', decimals @ ..] = rest {
+            let digits = decimals.iter().take_while(|c| c.is_ascii_digit()).count();
+            if digits > 0 {
+                tenths += u32::from(decimals[0] - b'0');
+            }
+            rest = &decimals[digits..];
+        }
+        let mut delay = Delay {
+            tenths,
+            proportional: false,
+            mandatory: false,
+        };
+        for &c in rest {
+            match c {
+                b'*' => delay.proportional = true,
+                b'/' => delay.mandatory = true,
+                _ => return None,
+            }
+        }
+        Some(delay)
+    }
+
+    /// Returns how long the terminal needs (per line affected, if proportional).
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros(u64::from(self.tenths) * 100)
+    }
+
+    /// Returns true if the delay is per line affected (`*`).
+    pub fn is_proportional(&self) -> bool {
+        self.proportional
+    }
+
+    /// Returns true if the delay is needed even on terminals with flow control (`/`).
+    pub fn is_mandatory(&self) -> bool {
+        self.mandatory
+    }
+}
+
 /// Expand a parameterized capability
 ///
 /// # Arguments
@@ -127,8 +187,25 @@ impl Variables {
 ///
 /// To be compatible with ncurses, `vars` should be the same between calls to `expand` for
 /// multiple capabilities for the same terminal.
+///
+/// Padding specifications (`$<5>`, `$<10*/>`, ...) are removed; use `expand_with_padding` to
+/// honor them.
 pub fn expand(cap: &[u8], params: &[Param], vars: &mut Variables) -> Result<Vec<u8>, Error> {
+    expand_with_padding(cap, params, vars, &mut |_, _| {})
+}
+
+/// Like `expand`, but calls `pad` at each padding specification with the output expanded so
+/// far, which it can extend with pad characters, or write out (leaving it empty) before
+/// waiting. Malformed padding specifications are removed.
+pub fn expand_with_padding(
+    cap: &[u8],
+    params: &[Param],
+    vars: &mut Variables,
+    pad: &mut dyn FnMut(&mut Vec<u8>, Delay),
+) -> Result<Vec<u8>, Error> {
     let mut state = Nothing;
+    // The padding specification being read
+    let mut spec = Vec::new();
 
     // expanded cap will only rarely be larger than the cap itself
     let mut output = Vec::with_capacity(cap.len());
@@ -159,15 +236,38 @@ pub fn expand(cap: &[u8], params: &[Param], vars: &mut Variables) -> Result<Vec<
                 if cur == '%' {
                     state = Percent;
                 } else if cur == '$' {
-                    state = Delay;
+                    state = Dollar;
                 } else {
                     output.push(c);
                 }
             }
+            Dollar => {
+                // Padding is only introduced by `$<`; any other `$` is literal.
+                old_state = Nothing;
+                if cur == '<' {
+                    spec.clear();
+                    state = Delay;
+                } else {
+                    output.push(b'$');
+                    match cur {
+                        '%' => state = Percent,
+                        '$' => state = Dollar,
+                        _ => {
+                            output.push(c);
+                            state = Nothing;
+                        }
+                    }
+                }
+            }
             Delay => {
                 old_state = Nothing;
                 if cur == '>' {
+                    if let Some(delay) = Delay::parse(&spec) {
+                        pad(&mut output, delay);
+                    }
                     state = Nothing;
+                } else {
+                    spec.push(c);
                 }
             }
             Percent => {
@@ -460,6 +560,9 @@ pub fn expand(cap: &[u8], params: &[Param], vars: &mut Variables) -> Result<Vec<
             state = Nothing;
         }
     }
+    if state == Dollar {
+        output.push(b'$');
+    }
     Ok(output)
 }
 
@@ -568,8 +671,9 @@ fn format(val: Param, op: FormatOp, flags: Flags) -> Result<Vec<u8>, Error> {
 #[cfg(test)]
 mod test {
     use super::Param::{self, Number, Words};
-    use super::{expand, Variables};
+    use super::{expand, expand_with_padding, Variables};
     use std::result::Result::Ok;
+    use std::time::Duration;
 
     #[test]
     fn test_basic_setabf() {
@@ -580,6 +684,46 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_padding() {
+        let mut vars = Variables::new();
+        for (cap, expected) in [
+            (&b"\x1B[H$<5>"[..], &b"\x1B[H"[..]),
+            (b"a$<10*>b$<5/>c$<2.5*/>d", b"abcd"),
+            (b"$5 $$<1>$", b"$5 $$"),
+            (b"$%p1%d", b"$7"),
+        ] {
+            assert_eq!(expand(cap, &[Number(7)], &mut vars), Ok(expected.to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_expand_with_padding() {
+        let mut delays = Vec::new();
+        let s = expand_with_padding(
+            b"a$<5>b$<10*>c$<2.5*/>d$<x>e",
+            &[],
+            &mut Variables::new(),
+            &mut |output, delay| {
+                output.push(b'_');
+                delays.push((
+                    delay.duration(),
+                    delay.is_proportional(),
+                    delay.is_mandatory(),
+                ));
+            },
+        );
+        assert_eq!(s, Ok(b"a_b_c_de".to_vec()));
+        assert_eq!(
+            delays,
+            [
+                (Duration::from_millis(5), false, false),
+                (Duration::from_millis(10), true, false),
+                (Duration::from_micros(2500), true, true),
+            ]
+        );
+    }
+
     #[test]
     fn test_multiple_int_constants() {
         assert_eq!(
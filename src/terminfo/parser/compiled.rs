@@ -14,7 +14,7 @@ use std::collections::HashMap;
 use std::io;
 use std::io::prelude::*;
 
-use crate::terminfo::Error::*;
+use crate::terminfo::Error::{self, *};
 use crate::terminfo::TermInfo;
 use crate::Result;
 
@@ -34,16 +34,101 @@ fn read_le_u32(r: &mut dyn io::Read) -> io::Result<u32> {
     r.read_exact(&mut buf).map(|()| u32::from_le_bytes(buf))
 }
 
-fn read_byte(r: &mut dyn io::Read) -> io::Result<u8> {
-    match r.bytes().next() {
-        Some(s) => s,
-        None => Err(io::Error::new(io::ErrorKind::Other, "end of file")),
+/// Read exactly `len` bytes of a section, failing with `MalformedTerminfo` if the input is too
+/// short to contain it.
+fn read_section(r: &mut dyn io::Read, len: usize, what: &'static str) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(len);
+    r.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(MalformedTerminfo(what).into());
     }
+    Ok(buf)
+}
+
+// According to the spec, these fields must be >= -1 where -1 means that the
+// feature is not
+// supported. Using 0 instead of -1 works because we skip sections with length
+// 0.
+macro_rules! read_nonneg {
+    ($header:ident) => {{
+        match read_le_u16(&mut $header)? as i16 {
+            n if n >= 0 => n as usize,
+            -1 => 0,
+            _ => return Err(InvalidLength.into()),
+        }
+    }};
 }
 
 /// Parse a compiled terminfo entry, using long capability names if `longnames`
 /// is true
+///
+/// Terminal names that aren't valid UTF-8 are rejected with `Error::NotUtf8` rather than decoded
+/// lossily. A malformed extended (`tic -x`) section is dropped, keeping the standard
+/// capabilities.
 pub fn parse(file: &mut dyn io::Read, longnames: bool) -> Result<TermInfo> {
+    parse_entry(file, longnames, &mut Warnings(None))
+}
+
+/// Parse a compiled terminfo entry like `parse`, but recover from the problems it can, returning
+/// them along with the entry.
+///
+/// Capabilities that can't be read (such as strings without a NUL terminator or with offsets
+/// past the end of the string table) are skipped, capabilities this crate doesn't know about are
+/// ignored, names that aren't valid UTF-8 are decoded lossily, and sections cut short are used
+/// as far as they go. Each of these is reported as the error `parse` would have failed with, as
+/// is a malformed extended section, which both drop.
+/// Entries too broken to get any capabilities from, such as those with a bad magic number or no
+/// names, still fail.
+pub fn parse_lenient(file: &mut dyn io::Read, longnames: bool) -> Result<(TermInfo, Vec<Error>)> {
+    let mut warnings = Vec::new();
+    let ti = parse_entry(file, longnames, &mut Warnings(Some(&mut warnings)))?;
+    Ok((ti, warnings))
+}
+
+/// Where `parse_lenient` collects the problems it recovers from; `parse` has none, and fails
+/// on them instead.
+struct Warnings<'a>(Option<&'a mut Vec<Error>>);
+
+impl Warnings<'_> {
+    /// Records `error`, or returns it if not recovering from errors.
+    fn warn(&mut self, error: Error) -> Result<()> {
+        match &mut self.0 {
+            Some(warnings) => {
+                warnings.push(error);
+                Ok(())
+            }
+            None => Err(error.into()),
+        }
+    }
+
+    /// Records `error` if recovering from errors, and otherwise ignores it.
+    fn note(&mut self, error: Error) {
+        if let Some(warnings) = &mut self.0 {
+            warnings.push(error);
+        }
+    }
+
+    /// Like `read_section`, but returns what's there of a truncated section if recovering.
+    fn read_section(
+        &mut self,
+        r: &mut dyn io::Read,
+        len: usize,
+        what: &'static str,
+    ) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(len);
+        r.take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len {
+            self.warn(MalformedTerminfo(what))?;
+        }
+        Ok(buf)
+    }
+}
+
+fn parse_entry(
+    file: &mut dyn io::Read,
+    longnames: bool,
+    warnings: &mut Warnings<'_>,
+) -> Result<TermInfo> {
     let (bnames, snames, nnames) = if longnames {
         (boolfnames, stringfnames, numfnames)
     } else {
@@ -52,134 +137,160 @@ pub fn parse(file: &mut dyn io::Read, longnames: bool) -> Result<TermInfo> {
 
     // Check magic number
     let mut buf = [0; 2];
-    file.read_exact(&mut buf)?;
+    file.read_exact(&mut buf)
+        .map_err(|_| MalformedTerminfo("header truncated"))?;
     let magic = u16::from_le_bytes(buf);
 
-    let read_number = match magic {
-        0x011A => read_le_u16,
-        0x021e => read_le_u32,
+    let (read_number, number_width) = match magic {
+        0x011A => (read_le_u16 as fn(&mut dyn io::Read) -> io::Result<u32>, 2),
+        0x021e => (read_le_u32 as fn(&mut dyn io::Read) -> io::Result<u32>, 4),
         _ => return Err(BadMagic(magic).into()),
     };
 
-    // According to the spec, these fields must be >= -1 where -1 means that the
-    // feature is not
-    // supported. Using 0 instead of -1 works because we skip sections with length
-    // 0.
-    macro_rules! read_nonneg {
-        () => {{
-            match read_le_u16(file)? as i16 {
-                n if n >= 0 => n as usize,
-                -1 => 0,
-                _ => return Err(InvalidLength.into()),
-            }
-        }};
-    }
-
-    let names_bytes = read_nonneg!();
-    let bools_bytes = read_nonneg!();
-    let numbers_count = read_nonneg!();
-    let string_offsets_count = read_nonneg!();
-    let string_table_bytes = read_nonneg!();
+    let header = read_section(file, 10, "header truncated")?;
+    let mut header = &header[..];
+
+    let names_bytes = read_nonneg!(header);
+    let bools_bytes = read_nonneg!(header);
+    let numbers_count = read_nonneg!(header);
+    let string_offsets_count = read_nonneg!(header);
+    // Like ncurses, read the size of the string table as unsigned so that tables over 32KiB
+    // (with offsets past 0x7FFF) can be read.
+    let string_table_bytes = match read_le_u16(&mut header)? {
+        0xFFFF => 0,
+        n => n as usize,
+    };
 
     if names_bytes == 0 {
         return Err(ShortNames.into());
     }
 
+    // Capabilities past the ones we know about are ignored when recovering from errors.
     if bools_bytes > boolnames.len() {
-        return Err(TooManyBools.into());
+        warnings.warn(TooManyBools)?;
     }
 
     if numbers_count > numnames.len() {
-        return Err(TooManyNumbers.into());
+        warnings.warn(TooManyNumbers)?;
     }
 
     if string_offsets_count > stringnames.len() {
-        return Err(TooManyStrings.into());
+        warnings.warn(TooManyStrings)?;
     }
 
-    // don't read NUL
-    let mut bytes = Vec::new();
-    file.take((names_bytes - 1) as u64)
-        .read_to_end(&mut bytes)?;
-    let names_str = match String::from_utf8(bytes) {
+    let mut names = read_section(file, names_bytes, "names section truncated")?;
+    // consume NUL
+    if names.pop() != Some(b'\0') {
+        warnings.warn(NamesMissingNull)?;
+    }
+    let names_str = match String::from_utf8(names) {
         Ok(s) => s,
-        Err(e) => return Err(NotUtf8(e.utf8_error()).into()),
+        Err(e) => {
+            warnings.warn(NotUtf8(e.utf8_error()))?;
+            String::from_utf8_lossy(e.as_bytes()).into_owned()
+        }
     };
 
     let term_names: Vec<String> = names_str.split('|').map(|s| s.to_owned()).collect();
-    // consume NUL
-    if read_byte(file)? != b'\0' {
-        return Err(NamesMissingNull.into());
-    }
 
-    let bools_map = (0..bools_bytes)
-        .filter_map(|i| match read_byte(file) {
-            Err(e) => Some(Err(e)),
-            Ok(1) => Some(Ok((bnames[i], true))),
-            Ok(_) => None,
-        })
-        .collect::<io::Result<HashMap<_, _>>>()?;
+    let mut bools_map = warnings
+        .read_section(file, bools_bytes, "boolean section truncated")?
+        .into_iter()
+        .zip(bnames)
+        .filter(|&(b, _)| b == 1)
+        .map(|(_, &name)| (name, true))
+        .collect::<HashMap<_, _>>();
 
     if (bools_bytes + names_bytes) % 2 == 1 {
-        read_byte(file)?; // compensate for padding
+        // compensate for padding
+        warnings.read_section(file, 1, "boolean section padding truncated")?;
     }
 
-    let numbers_map = (0..numbers_count)
-        .filter_map(|i| match read_number(file) {
-            Ok(0xFFFF) => None,
-            Ok(n) => Some(Ok((nnames[i], n))),
-            Err(e) => Some(Err(e)),
-        })
-        .collect::<io::Result<HashMap<_, _>>>()?;
+    let numbers = warnings.read_section(
+        file,
+        numbers_count * number_width,
+        "numbers section truncated",
+    )?;
+    let mut numbers_map = HashMap::new();
+    // Negative numbers (-1 and -2) mark absent and cancelled capabilities.
+    let sign_bit = 1 << (number_width * 8 - 1);
+    for (mut number, &name) in numbers.chunks_exact(number_width).zip(nnames) {
+        let n = read_number(&mut number)?;
+        if n & sign_bit == 0 {
+            numbers_map.insert(name, n);
+        }
+    }
 
-    let string_map: HashMap<&str, Vec<u8>> = if string_offsets_count > 0 {
-        let string_offsets = (0..string_offsets_count)
-            .map(|_| {
-                let mut buf = [0; 2];
-                file.read_exact(&mut buf).map(|()| u16::from_le_bytes(buf))
-            })
-            .collect::<io::Result<Vec<_>>>()?;
-
-        let mut string_table = Vec::new();
-        file.take(string_table_bytes as u64)
-            .read_to_end(&mut string_table)?;
-
-        string_offsets
-            .into_iter()
-            .enumerate()
-            .filter(|&(_, offset)| {
-                // non-entry
-                offset != 0xFFFF
-            })
-            .map(|(i, offset)| {
-                let offset = offset as usize;
-
-                let name = if snames[i] == "_" {
-                    stringfnames[i]
-                } else {
-                    snames[i]
-                };
-
-                if offset == 0xFFFE {
-                    // undocumented: FFFE indicates cap@, which means the capability
-                    // is not present
-                    // unsure if the handling for this is correct
-                    return Ok((name, Vec::new()));
-                }
+    let mut string_map: HashMap<&str, Vec<u8>> = HashMap::new();
+    if string_offsets_count > 0 {
+        let string_offsets = warnings.read_section(
+            file,
+            string_offsets_count * 2,
+            "string offsets section truncated",
+        )?;
+        let string_table =
+            warnings.read_section(file, string_table_bytes, "string table truncated")?;
+
+        let offsets = string_offsets
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .zip(snames.iter().zip(stringfnames));
+        for (offset, (&short, &long)) in offsets {
+            // non-entry
+            if offset == 0xFFFF {
+                continue;
+            }
+            let offset = offset as usize;
+            let name = if short == "_" { long } else { short };
+
+            if offset == 0xFFFE {
+                // undocumented: FFFE indicates cap@, which means the capability
+                // is not present
+                // unsure if the handling for this is correct
+                string_map.insert(name, Vec::new());
+                continue;
+            }
 
-                // Find the offset of the NUL we want to go to
-                let nulpos = string_table[offset..string_table_bytes]
-                    .iter()
-                    .position(|&b| b == 0);
-                match nulpos {
-                    Some(len) => Ok((name, string_table[offset..offset + len].to_vec())),
-                    None => Err(crate::Error::TerminfoParsing(StringsMissingNull)),
+            if offset >= string_table.len() {
+                warnings.warn(MalformedTerminfo("string offset past end of string table"))?;
+                continue;
+            }
+
+            // Find the offset of the NUL we want to go to
+            let nulpos = string_table[offset..].iter().position(|&b| b == 0);
+            match nulpos {
+                Some(len) => {
+                    string_map.insert(name, string_table[offset..offset + len].to_vec());
                 }
-            })
-            .collect::<Result<HashMap<_, _>>>()?
+                None => warnings.warn(StringsMissingNull)?,
+            }
+        }
+    }
+
+    // Entries compiled with `tic -x` continue with the extended capabilities, starting at an
+    // even offset.
+    let skip = if string_offsets_count == 0 {
+        string_table_bytes
     } else {
-        HashMap::new()
+        0
     };
+    let main_bytes = names_bytes
+        + bools_bytes
+        + numbers_count * number_width
+        + string_offsets_count * 2
+        + string_table_bytes;
+    let bools_padding = (bools_bytes + names_bytes) % 2;
+    let skip = skip + (main_bytes + bools_padding) % 2;
+    match parse_extended(file, skip, number_width) {
+        Ok((ext_bools, ext_strings)) => {
+            bools_map.extend(ext_bools);
+            string_map.extend(ext_strings);
+        }
+        // Give up on the extended capabilities, keeping the others: they're an ncurses
+        // extension, and other implementations ignore them too.
+        Err(crate::Error::TerminfoParsing(e)) => warnings.note(e),
+        Err(_) => {}
+    }
 
     // And that's all there is to it
     Ok(TermInfo {
@@ -190,6 +301,106 @@ pub fn parse(file: &mut dyn io::Read, longnames: bool) -> Result<TermInfo> {
     })
 }
 
+/// The extended boolean and string capabilities of an entry.
+type Extended = (HashMap<&'static str, bool>, HashMap<&'static str, Vec<u8>>);
+
+/// Parse the extended capabilities following the standard ones, starting `skip` bytes ahead.
+///
+/// Only the ones in `extboolnames` and `extstringnames` are kept.
+fn parse_extended(file: &mut dyn io::Read, skip: usize, number_width: usize) -> Result<Extended> {
+    let mut bools_map = HashMap::new();
+    let mut string_map = HashMap::new();
+    io::copy(&mut file.take(skip as u64), &mut io::sink())?;
+    let mut header = Vec::with_capacity(10);
+    file.take(10).read_to_end(&mut header)?;
+    if header.is_empty() {
+        return Ok((bools_map, string_map));
+    }
+    if header.len() != 10 {
+        return Err(MalformedTerminfo("extended header truncated").into());
+    }
+    let mut header = &header[..];
+    let ext_bools_count = read_nonneg!(header);
+    let ext_numbers_count = read_nonneg!(header);
+    let ext_strings_count = read_nonneg!(header);
+    let _ext_items = read_nonneg!(header);
+    let ext_table_bytes = read_nonneg!(header);
+
+    let ext_bools = read_section(file, ext_bools_count, "extended boolean section truncated")?;
+    if ext_bools_count % 2 == 1 {
+        read_section(file, 1, "extended boolean padding truncated")?;
+    }
+    read_section(
+        file,
+        ext_numbers_count * number_width,
+        "extended numbers section truncated",
+    )?;
+    let ext_string_offsets = read_section(
+        file,
+        ext_strings_count * 2,
+        "extended string offsets truncated",
+    )?;
+    let ext_name_offsets = read_section(
+        file,
+        (ext_bools_count + ext_numbers_count + ext_strings_count) * 2,
+        "extended name offsets truncated",
+    )?;
+    let ext_table = read_section(file, ext_table_bytes, "extended string table truncated")?;
+
+    // Negative offsets mark absent and cancelled capabilities.
+    let offsets = |bytes: &[u8]| -> Vec<Option<usize>> {
+        bytes
+            .chunks_exact(2)
+            .map(|b| match i16::from_le_bytes([b[0], b[1]]) {
+                n if n >= 0 => Some(n as usize),
+                _ => None,
+            })
+            .collect()
+    };
+    let string_at = |offset: usize| -> Result<&[u8]> {
+        let s = ext_table
+            .get(offset..)
+            .ok_or(MalformedTerminfo("string offset past end of string table"))?;
+        match s.iter().position(|&b| b == 0) {
+            Some(len) => Ok(&s[..len]),
+            None => Err(crate::Error::TerminfoParsing(StringsMissingNull)),
+        }
+    };
+
+    let ext_string_offsets = offsets(&ext_string_offsets);
+    // The names follow the string values in the table.
+    let mut names_start = 0;
+    for &offset in ext_string_offsets.iter().flatten() {
+        names_start = names_start.max(offset + string_at(offset)?.len() + 1);
+    }
+    let ext_names = offsets(&ext_name_offsets)
+        .into_iter()
+        .map(|offset| {
+            let offset = offset.ok_or(MalformedTerminfo("extended capability without a name"))?;
+            string_at(names_start + offset)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let lookup = |names: &'static [&'static str], name: &[u8]| {
+        names.iter().copied().find(|n| n.as_bytes() == name)
+    };
+
+    for (value, name) in ext_bools.iter().zip(&ext_names) {
+        match lookup(extboolnames, name) {
+            Some(name) if *value == 1 => {
+                bools_map.insert(name, true);
+            }
+            _ => {}
+        }
+    }
+    let ext_string_names = &ext_names[ext_bools_count + ext_numbers_count..];
+    for (offset, name) in ext_string_offsets.iter().zip(ext_string_names) {
+        if let (Some(offset), Some(name)) = (offset, lookup(extstringnames, name)) {
+            string_map.insert(name, string_at(*offset)?.to_vec());
+        }
+    }
+    Ok((bools_map, string_map))
+}
+
 #[cfg(test)]
 mod test {
 
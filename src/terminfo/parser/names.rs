@@ -551,3 +551,7 @@ pub static stringnames: &[&str] =
       "elhlm", "elohlm", "erhlm", "ethlm", "evhlm", "sgr1", "slength", "OTi2", "OTrs", "OTnl",
       "OTbs", "OTko", "OTma", "OTG2", "OTG3", "OTG1", "OTG4", "OTGR", "OTGL", "OTGU", "OTGD",
       "OTGH", "OTGV", "OTGC", "meml", "memu", "box1"];
+
+// The extended capabilities (from `tic -x`) that are kept; the others are skipped.
+pub static extboolnames: &[&str] = &["RGB", "Tc"];
+pub static extstringnames: &[&str] = &["Ms", "Se", "Ss", "Sync", "setrgbb", "setrgbf"];
@@ -0,0 +1,179 @@
+//! terminfo source format parsing (terminfo(5)), as printed by `infocmp`
+
+use std::collections::HashMap;
+
+use crate::terminfo::parser::names::{
+    boolnames, extboolnames, extstringnames, numnames, stringnames,
+};
+use crate::terminfo::Error::*;
+use crate::terminfo::TermInfo;
+use crate::Result;
+
+/// Splits `text` into its comma-separated fields, skipping comment lines.
+///
+/// Escaped commas (`\,`) don't end a field; the escapes themselves are left in place.
+fn fields(text: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut start = 0;
+        let mut escaped = false;
+        for (i, b) in line.bytes().enumerate() {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b',' => {
+                    fields.push(line[start..i].trim());
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if !line[start..].trim().is_empty() {
+            fields.push(line[start..].trim());
+        }
+    }
+    fields
+}
+
+/// Parses a number in decimal, octal (with a leading `0`) or hex (with a leading `0x`).
+fn parse_number(s: &str) -> Option<u32> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if s.len() > 1 && s.starts_with('0') {
+        u32::from_str_radix(&s[1..], 8).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+/// Expands the escapes in a string capability's value.
+fn unescape(s: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\\' => {
+                let c = bytes
+                    .next()
+                    .ok_or(MalformedTerminfo("string ends with a backslash"))?;
+                out.push(match c {
+                    b'E' | b'e' => b'\x1B',
+                    b'n' | b'l' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'b' => b'\x08',
+                    b'f' => b'\x0C',
+                    b'a' => b'\x07',
+                    b's' => b' ',
+                    b'0'..=b'7' => {
+                        let mut n = u32::from(c - b'0');
+                        for _ in 0..2 {
+                            match bytes.clone().next() {
+                                Some(d @ b'0'..=b'7') => {
+                                    bytes.next();
+                                    n = n * 8 + u32::from(d - b'0');
+                                }
+                                _ => break,
+                            }
+                        }
+                        // Like tic, store NUL as \200 so that it doesn't end the string.
+                        match n {
+                            0 => 0x80,
+                            n => u8::try_from(n)
+                                .map_err(|_| MalformedTerminfo("octal escape out of range"))?,
+                        }
+                    }
+                    // \\, \^, \, and \: stand for themselves, as do unknown escapes.
+                    c => c,
+                });
+            }
+            b'^' => {
+                let c = bytes
+                    .next()
+                    .ok_or(MalformedTerminfo("string ends with a caret"))?;
+                out.push(if c == b'?' { 0x7F } else { c & 0x1F });
+            }
+            // %-escapes are expanded along with the parameters, see `parm::expand`.
+            b => out.push(b),
+        }
+    }
+    Ok(out)
+}
+
+/// Finds the static name for a capability, to use as a key.
+fn lookup(names: &'static [&'static str], name: &str) -> Option<&'static str> {
+    names.iter().copied().find(|&n| n == name)
+}
+
+/// Parse a terminfo entry in the source format, e.g. as printed by `infocmp`.
+///
+/// Only the standard capabilities (by their short names) are kept, along with the few extended
+/// capabilities the compiled format parser keeps. `use=` isn't supported, as it would
+/// require looking up other entries; use `infocmp` without `-u` to print complete entries.
+pub fn parse(text: &str) -> Result<TermInfo> {
+    let mut fields = fields(text).into_iter();
+    let names = fields
+        .next()
+        .filter(|names| !names.is_empty())
+        .ok_or(ShortNames)?;
+
+    let mut bools = HashMap::new();
+    let mut numbers = HashMap::new();
+    let mut strings = HashMap::new();
+    for field in fields {
+        if let Some((name, value)) = field.split_once('=') {
+            if name == "use" {
+                return Err(MalformedTerminfo("use= is not supported").into());
+            }
+            if let Some(name) = lookup(stringnames, name).or_else(|| lookup(extstringnames, name)) {
+                strings.insert(name, unescape(value)?);
+            }
+        } else if let Some((name, value)) = field.split_once('#') {
+            let value = parse_number(value).ok_or(MalformedTerminfo("invalid number"))?;
+            if let Some(name) = lookup(numnames, name) {
+                numbers.insert(name, value);
+            }
+        } else if field.ends_with('@') {
+            // Cancelled capabilities only matter with use=.
+        } else if let Some(name) = lookup(boolnames, field).or_else(|| lookup(extboolnames, field))
+        {
+            bools.insert(name, true);
+        }
+    }
+
+    Ok(TermInfo {
+        names: names.split('|').map(|s| s.to_owned()).collect(),
+        bools,
+        numbers,
+        strings,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fields, unescape};
+
+    #[test]
+    fn test_fields() {
+        assert_eq!(
+            fields("# comment\nab|a b,\n\tam, cols#80,\n\tx=a\\,b, y=\\\\,\n"),
+            ["ab|a b", "am", "cols#80", "x=a\\,b", "y=\\\\"]
+        );
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("\\E[%p1%dm").unwrap(), b"\x1B[%p1%dm");
+        assert_eq!(unescape("^G^[^?\\^").unwrap(), b"\x07\x1B\x7F^");
+        assert_eq!(
+            unescape("\\0\\177\\01x\\s\\,\\:").unwrap(),
+            b"\x80\x7F\x01x ,:"
+        );
+        assert!(unescape("\\400").is_err());
+        assert!(unescape("a\\").is_err());
+    }
+}
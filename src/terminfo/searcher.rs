@@ -12,9 +12,11 @@
 //!
 //! Does not support hashed database, only filesystem!
 
-use std::env;
+use std::collections::BTreeSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::terminfo::Environment;
 
 // The default terminfo location should be /usr/lib/terminfo but that's not guaranteed, so we check
 // a few more locations. See https://tldp.org/HOWTO/Text-Terminal-HOWTO-16.html#ss16.2
@@ -25,27 +27,112 @@ const DEFAULT_LOCATIONS: &[&str] = &[
     "/lib/terminfo",
     #[cfg(target_os = "haiku")]
     "/boot/system/data/terminfo",
+    // The BSDs' base systems don't always ship a terminfo directory (NetBSD only has the hashed
+    // /usr/share/misc/terminfo.cdb, which we can't read), but ncurses from ports and pkgsrc
+    // installs one.
+    #[cfg(any(target_os = "freebsd", target_os = "openbsd", target_os = "dragonfly"))]
+    "/usr/local/share/terminfo",
+    #[cfg(target_os = "netbsd")]
+    "/usr/pkg/share/terminfo",
+    // Homebrew, for entries the system database lacks. Its ncurses is keg-only, so its database
+    // isn't linked into the prefix. (/opt/homebrew on Apple silicon, /usr/local on Intel.)
+    #[cfg(target_os = "macos")]
+    "/opt/homebrew/opt/ncurses/share/terminfo",
+    #[cfg(target_os = "macos")]
+    "/opt/homebrew/share/terminfo",
+    #[cfg(target_os = "macos")]
+    "/usr/local/opt/ncurses/share/terminfo",
+    #[cfg(target_os = "macos")]
+    "/usr/local/share/terminfo",
 ];
 
+/// Returns true if `term` can be looked up without leaving the database directories.
+///
+/// Like ncurses, reject names containing path separators, as well as `.` and `..`.
+fn is_valid_name(term: &str) -> bool {
+    !matches!(term, "" | "." | "..") && !term.contains(['/', '\\', '\0'])
+}
+
+/// The file names an entry for `term` may have: with the `flate2` feature, entries may also be
+/// gzip-compressed with a `.gz` extension.
+fn entry_names(term: &str) -> impl Iterator<Item = String> {
+    let gz = if cfg!(feature = "flate2") {
+        Some(format!("{}.gz", term))
+    } else {
+        None
+    };
+    std::iter::once(term.to_owned()).chain(gz)
+}
+
 /// Return path to database entry for `term`
+///
+/// Returns `None` for names that aren't valid terminal names, such as those containing path
+/// separators.
 pub fn get_dbpath_for_term(term: &str) -> Option<PathBuf> {
+    get_dbpath_for_term_in(term, &Environment::from_process())
+}
+
+/// Return path to database entry for `term`, searching the databases named by `environment`
+/// rather than by the process environment.
+pub fn get_dbpath_for_term_in(term: &str, environment: &Environment) -> Option<PathBuf> {
+    if !is_valid_name(term) {
+        return None;
+    }
+    let first_char = term.chars().next()?;
+
+    // Look for the terminal in all of the search directories
+    for p in search_dirs(environment) {
+        let metadata = match fs::metadata(&p) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.is_file() {
+            // A single entry rather than a database; use it if it's the one we're looking for.
+            if p.file_name()
+                .is_some_and(|name| entry_names(term).any(|n| name == &n[..]))
+            {
+                return Some(p);
+            }
+        } else {
+            // on some installations the dir is named after the hex of the char
+            // (e.g. OS X)
+            for subdir in [first_char.to_string(), format!("{:x}", first_char as usize)] {
+                for name in entry_names(term) {
+                    let entry = p.join(&subdir).join(name);
+                    if fs::metadata(&entry).is_ok() {
+                        return Some(entry);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Returns the databases to search, in order.
+fn search_dirs(environment: &Environment) -> Vec<PathBuf> {
     let mut dirs_to_search = Vec::new();
     let mut default_locations = DEFAULT_LOCATIONS.iter().map(PathBuf::from);
-    let first_char = match term.chars().next() {
-        Some(c) => c,
-        None => return None,
-    };
 
     // From the manual.
     //
     // > The  environment  variable TERMINFO is checked first, for a terminal
     // > database containing the terminal description.
-    if let Some(dir) = env::var_os("TERMINFO") {
-        dirs_to_search.push(PathBuf::from(dir));
+    //
+    // Some setups point it at the compiled entry itself instead; see below.
+    if let Some(dir) = &environment.terminfo {
+        dirs_to_search.push(dir.clone());
     }
 
     // > Next, ncurses looks in $HOME/.terminfo for a compiled description.
-    if let Some(mut homedir) = home::home_dir() {
+    //
+    // An empty home directory would turn this into a search relative to the current directory,
+    // so skip it (and carry on with the rest of the search) instead.
+    if let Some(mut homedir) = environment
+        .home
+        .clone()
+        .filter(|p| !p.as_os_str().is_empty())
+    {
         homedir.push(".terminfo");
         dirs_to_search.push(homedir)
     }
@@ -57,7 +144,7 @@ pub fn get_dbpath_for_term(term: &str) -> Option<PathBuf> {
     // > An  empty  pathname  (i.e.,  if  the  variable begins or ends with a
     // > colon, or contains adjacent colons) is interpreted as the system location
     // > /usr/share/terminfo.
-    if let Ok(dirs) = env::var("TERMINFO_DIRS") {
+    if let Some(dirs) = &environment.terminfo_dirs {
         for i in dirs.split(':') {
             if i.is_empty() {
                 dirs_to_search.extend(&mut default_locations);
@@ -72,26 +159,49 @@ pub fn get_dbpath_for_term(term: &str) -> Option<PathBuf> {
     // NOTE: We only append these to `dirs_to_search` once. If we've already added these
     // directories as specified in `TERMINFO_DIRS`, this operation will be a no-op.
     dirs_to_search.extend(&mut default_locations);
+    dirs_to_search
+}
 
-    // Look for the terminal in all of the search directories
-    for mut p in dirs_to_search {
-        if fs::metadata(&p).is_ok() {
-            p.push(first_char.to_string());
-            p.push(term);
-            if fs::metadata(&p).is_ok() {
-                return Some(p);
-            }
-            p.pop();
-            p.pop();
+/// Returns the names of all the terminals in the database, sorted, like `toe -a`.
+///
+/// Directories that can't be read are skipped.
+pub fn list_terminals() -> Vec<String> {
+    list_terminals_in(&Environment::from_process())
+}
 
-            // on some installations the dir is named after the hex of the char
-            // (e.g. OS X)
-            p.push(format!("{:x}", first_char as usize));
-            p.push(term);
-            if fs::metadata(&p).is_ok() {
-                return Some(p);
+/// Returns the names of all the terminals in the databases named by `environment`, sorted.
+pub fn list_terminals_in(environment: &Environment) -> Vec<String> {
+    let mut names = BTreeSet::new();
+    let mut add = |path: &Path| {
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            let name = if cfg!(feature = "flate2") {
+                name.strip_suffix(".gz").unwrap_or(name)
+            } else {
+                name
+            };
+            names.insert(name.to_owned());
+        }
+    };
+    for dir in search_dirs(environment) {
+        if dir.is_file() {
+            add(&dir);
+            continue;
+        }
+        let subdirs = match fs::read_dir(&dir) {
+            Ok(subdirs) => subdirs,
+            Err(_) => continue,
+        };
+        for subdir in subdirs.flatten() {
+            let entries = match fs::read_dir(subdir.path()) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                if entry.path().is_file() {
+                    add(&entry.path());
+                }
             }
         }
     }
-    None
+    names.into_iter().collect()
 }
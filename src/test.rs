@@ -0,0 +1,311 @@
+//! Utilities for testing code that writes to a `Terminal`.
+//!
+//! Requires the `test-util` feature.
+
+use std::collections::HashMap;
+use std::io;
+use std::io::prelude::*;
+
+use crate::color;
+use crate::terminfo::{TermInfo, TerminfoTerminal, ANSI_SETAB, ANSI_SETAF};
+use crate::Attr;
+use crate::Result;
+use crate::Terminal;
+
+/// Returns the fixed `TermInfo` used by `TestTerminal`.
+///
+/// This is a 256-color, xterm-like description with the usual attributes and cursor movement
+/// capabilities.
+pub fn terminfo() -> TermInfo {
+    let mut strings = HashMap::new();
+    strings.insert("sgr0", b"\x1B[m".to_vec());
+    strings.insert("bold", b"\x1B[1m".to_vec());
+    strings.insert("dim", b"\x1B[2m".to_vec());
+    strings.insert("sitm", b"\x1B[3m".to_vec());
+    strings.insert("ritm", b"\x1B[23m".to_vec());
+    strings.insert("smul", b"\x1B[4m".to_vec());
+    strings.insert("rmul", b"\x1B[24m".to_vec());
+    strings.insert("blink", b"\x1B[5m".to_vec());
+    strings.insert("smso", b"\x1B[7m".to_vec());
+    strings.insert("rmso", b"\x1B[27m".to_vec());
+    strings.insert("rev", b"\x1B[7m".to_vec());
+    strings.insert("invis", b"\x1B[8m".to_vec());
+    strings.insert("setaf", ANSI_SETAF.to_vec());
+    strings.insert("setab", ANSI_SETAB.to_vec());
+    strings.insert("cuu1", b"\x1B[A".to_vec());
+    strings.insert("el", b"\x1B[K".to_vec());
+    strings.insert("cr", b"\r".to_vec());
+    strings.insert("Sync", b"\x1B[?2026%?%p1%{1}%-%tl%eh%;".to_vec());
+
+    let mut numbers = HashMap::new();
+    numbers.insert("colors", 256);
+    numbers.insert("cols", 80);
+    numbers.insert("lines", 24);
+
+    TermInfo {
+        names: vec!["xterm-term-test".to_owned()],
+        bools: HashMap::new(),
+        numbers,
+        strings,
+    }
+}
+
+/// An in-memory Terminal that records everything written to it, including escape sequences.
+///
+/// Uses the description returned by `terminfo()`, so the emitted bytes are the same on every
+/// platform and in every environment.
+///
+/// # Examples
+///
+/// ```
+/// use term::test::TestTerminal;
+/// use term::Terminal;
+///
+/// let mut t = TestTerminal::new();
+/// t.fg(term::color::RED).unwrap();
+/// assert_eq!(t.written(), b"\x1B[31m");
+/// ```
+#[derive(Clone, Debug)]
+pub struct TestTerminal {
+    inner: TerminfoTerminal<Vec<u8>>,
+}
+
+impl TestTerminal {
+    /// Create a new, empty TestTerminal.
+    pub fn new() -> TestTerminal {
+        TestTerminal {
+            inner: TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo()),
+        }
+    }
+
+    /// Returns everything written to the terminal so far.
+    pub fn written(&self) -> &[u8] {
+        self.inner.get_ref()
+    }
+
+    /// Discards everything written to the terminal so far.
+    pub fn clear(&mut self) {
+        self.inner.get_mut().clear()
+    }
+}
+
+impl Default for TestTerminal {
+    fn default() -> Self {
+        TestTerminal::new()
+    }
+}
+
+impl Terminal for TestTerminal {
+    type Output = Vec<u8>;
+
+    fn fg(&mut self, color: color::Color) -> Result<()> {
+        self.inner.fg(color)
+    }
+
+    fn bg(&mut self, color: color::Color) -> Result<()> {
+        self.inner.bg(color)
+    }
+
+    fn set_colors(&mut self, fg: color::Color, bg: color::Color) -> Result<()> {
+        self.inner.set_colors(fg, bg)
+    }
+
+    fn attr(&mut self, attr: Attr) -> Result<()> {
+        self.inner.attr(attr)
+    }
+
+    fn supports_attr(&self, attr: Attr) -> bool {
+        self.inner.supports_attr(attr)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.inner.reset()
+    }
+
+    fn supports_reset(&self) -> bool {
+        self.inner.supports_reset()
+    }
+
+    fn supports_color(&self) -> bool {
+        self.inner.supports_color()
+    }
+
+    fn supports_color_index(&self, color: color::Color) -> bool {
+        self.inner.supports_color_index(color)
+    }
+
+    fn cursor_up(&mut self) -> Result<()> {
+        self.inner.cursor_up()
+    }
+
+    fn delete_line(&mut self) -> Result<()> {
+        self.inner.delete_line()
+    }
+
+    fn carriage_return(&mut self) -> Result<()> {
+        self.inner.carriage_return()
+    }
+
+    fn begin_sync_update(&mut self) -> Result<()> {
+        self.inner.begin_sync_update()
+    }
+
+    fn end_sync_update(&mut self) -> Result<()> {
+        self.inner.end_sync_update()
+    }
+
+    fn hyperlink(&mut self, url: &str, text: &str) -> Result<()> {
+        self.inner.hyperlink(url, text)
+    }
+
+    fn term_name(&self) -> Option<&str> {
+        self.inner.term_name()
+    }
+
+    fn get_ref(&self) -> &Vec<u8> {
+        self.inner.get_ref()
+    }
+
+    fn get_mut(&mut self) -> &mut Vec<u8> {
+        self.inner.get_mut()
+    }
+
+    fn into_inner(self) -> Vec<u8>
+    where
+        Self: Sized,
+    {
+        self.inner.into_inner()
+    }
+}
+
+impl Write for TestTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestTerminal;
+    use crate::color;
+    use crate::{Attr, Sanitize, Terminal};
+    use std::io::prelude::*;
+
+    #[test]
+    fn test_records_sequences() {
+        let mut t = TestTerminal::new();
+        t.fg(color::RED).unwrap();
+        t.bg(color::BRIGHT_BLUE).unwrap();
+        t.attr(Attr::Bold(true)).unwrap();
+        write!(t, "hi").unwrap();
+        t.reset().unwrap();
+        assert_eq!(t.written(), b"\x1B[31m\x1B[104m\x1B[1mhi\x1B[m");
+
+        t.clear();
+        t.fg(200).unwrap();
+        assert_eq!(t.written(), b"\x1B[38;5;200m");
+    }
+
+    #[test]
+    fn test_set_colors() {
+        let mut t = TestTerminal::new();
+        t.set_colors(color::RED, color::BLUE).unwrap();
+        assert_eq!(t.written(), b"\x1B[31m\x1B[44m");
+
+        t.clear();
+        assert_eq!(
+            t.set_colors(color::RED, 256),
+            Err(crate::Error::ColorOutOfRange)
+        );
+        assert_eq!(t.written(), b"");
+    }
+
+    #[test]
+    fn test_write_styled() {
+        let mut t = TestTerminal::new();
+        t.write_styled("hi", Some(color::RED), None, &[Attr::Bold(true)])
+            .unwrap();
+        assert_eq!(t.written(), b"\x1B[31m\x1B[1mhi\x1B[m");
+
+        t.clear();
+        t.write_styled("plain", None, None, &[]).unwrap();
+        assert_eq!(t.written(), b"plain");
+    }
+
+    #[test]
+    fn test_styled_line() {
+        let mut t = TestTerminal::new();
+        let bold = [Attr::Bold(true)];
+        t.styled_line(&[
+            (Some(color::RED), &bold, "a"),
+            (Some(color::RED), &bold, "b"),
+            (Some(color::GREEN), &bold, "c"),
+            (Some(color::GREEN), &[], "d"),
+            (None, &[], "e"),
+            (Some(color::BLUE), &[], "f"),
+            (None, &[], "g"),
+        ])
+        .unwrap();
+        assert_eq!(
+            t.written(),
+            b"\x1B[31m\x1B[1mab\x1B[32mc\x1B[m\x1B[32md\x1B[me\x1B[34mf\x1B[mg"
+        );
+
+        t.clear();
+        t.styled_line(&[(None, &[], "plain")]).unwrap();
+        assert_eq!(t.written(), b"plain");
+    }
+
+    #[test]
+    fn test_write_sanitized() {
+        let text = "a\x1B[31mb\tc\r\nd\x07\x7F\u{9B}e";
+        let mut t = TestTerminal::new();
+        t.write_sanitized(text, Sanitize::Strip).unwrap();
+        assert_eq!(t.written(), "a[31mb\tc\nde".as_bytes());
+
+        t.clear();
+        t.write_sanitized(text, Sanitize::Visualize).unwrap();
+        assert_eq!(
+            t.written(),
+            "a\u{241B}[31mb\tc\u{240D}\nd\u{2407}\u{2421}\u{FFFD}e".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_write_bytes() {
+        let mut t = TestTerminal::new();
+        t.fg(color::RED).unwrap();
+        t.write_bytes(b"caf\xE9\x1B").unwrap();
+        t.reset().unwrap();
+        assert_eq!(t.written(), b"\x1B[31mcaf\xE9\x1B\x1B[m");
+    }
+
+    #[test]
+    fn test_hyperlink() {
+        let mut t = TestTerminal::new();
+        t.hyperlink("file:///tmp/a.rs", "a.rs").unwrap();
+        assert_eq!(
+            t.written(),
+            b"\x1B]8;;file:///tmp/a.rs\x1B\\a.rs\x1B]8;;\x1B\\"
+        );
+
+        t.clear();
+        assert_eq!(
+            t.hyperlink("file:///tmp/\x1B]8;;", "a.rs"),
+            Err(crate::Error::InvalidControlCharacter)
+        );
+        assert_eq!(t.written(), b"");
+    }
+
+    #[test]
+    fn test_sync_update() {
+        let mut t = TestTerminal::new();
+        t.begin_sync_update().unwrap();
+        t.end_sync_update().unwrap();
+        assert_eq!(t.written(), b"\x1B[?2026h\x1B[?2026l");
+    }
+}
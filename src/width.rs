@@ -0,0 +1,288 @@
+//! Measuring the displayed width of terminal output.
+
+// SORTED! We binary search these.
+static ZERO_WIDTH: &[(char, char)] = &[
+    ('\u{0300}', '\u{036F}'),
+    ('\u{0483}', '\u{0489}'),
+    ('\u{0591}', '\u{05BD}'),
+    ('\u{0610}', '\u{061A}'),
+    ('\u{064B}', '\u{065F}'),
+    ('\u{1AB0}', '\u{1AFF}'),
+    ('\u{1DC0}', '\u{1DFF}'),
+    ('\u{200B}', '\u{200F}'),
+    ('\u{202A}', '\u{202E}'),
+    ('\u{2060}', '\u{2064}'),
+    ('\u{20D0}', '\u{20FF}'),
+    ('\u{FE00}', '\u{FE0F}'),
+    ('\u{FE20}', '\u{FE2F}'),
+    ('\u{FEFF}', '\u{FEFF}'),
+    ('\u{E0100}', '\u{E01EF}'),
+];
+
+static WIDE: &[(char, char)] = &[
+    ('\u{1100}', '\u{115F}'),
+    ('\u{231A}', '\u{231B}'),
+    ('\u{2E80}', '\u{303E}'),
+    ('\u{3041}', '\u{33FF}'),
+    ('\u{3400}', '\u{4DBF}'),
+    ('\u{4E00}', '\u{9FFF}'),
+    ('\u{A000}', '\u{A4CF}'),
+    ('\u{A960}', '\u{A97F}'),
+    ('\u{AC00}', '\u{D7A3}'),
+    ('\u{F900}', '\u{FAFF}'),
+    ('\u{FE10}', '\u{FE19}'),
+    ('\u{FE30}', '\u{FE6F}'),
+    ('\u{FF00}', '\u{FF60}'),
+    ('\u{FFE0}', '\u{FFE6}'),
+    ('\u{1F300}', '\u{1F64F}'),
+    ('\u{1F900}', '\u{1F9FF}'),
+    ('\u{20000}', '\u{2FFFD}'),
+    ('\u{30000}', '\u{3FFFD}'),
+];
+
+fn in_table(table: &[(char, char)], c: char) -> bool {
+    match table.binary_search_by_key(&c, |&(start, _)| start) {
+        Ok(_) => true,
+        Err(0) => false,
+        Err(idx) => c <= table[idx - 1].1,
+    }
+}
+
+fn char_width(c: char) -> usize {
+    if c.is_control() || in_table(ZERO_WIDTH, c) {
+        0
+    } else if in_table(WIDE, c) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Where the parser is in the output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// In text, possibly in the middle of a UTF-8 character
+    #[default]
+    Text,
+    /// After an `ESC`
+    Escape,
+    /// In a CSI sequence (such as a color), up to its final byte
+    Csi,
+    /// In an OSC, DCS, SOS, PM or APC sequence (such as a hyperlink), up to BEL or ST
+    String,
+    /// After an `ESC` in a string sequence, which ends it if followed by `\`
+    StringEscape,
+    /// In the intermediates of another escape sequence, up to its final character
+    Intermediate,
+}
+
+/// What a piece of output does to the cursor.
+enum Step {
+    /// Moves it right by this many columns
+    Width(usize),
+    /// Moves it to the start of the line (a newline or carriage return)
+    LineStart,
+    /// Moves it to the next tab stop
+    Tab,
+    /// Moves it left by one column, unless it's at the start of the line
+    Backspace,
+}
+
+/// Tells text from escape sequences in terminal output, a byte at a time, so that the output can
+/// be split anywhere (even within an escape sequence or a character).
+#[derive(Debug, Default, Clone)]
+struct Parser {
+    state: State,
+    /// The start of a UTF-8 character, and how many bytes the whole character takes.
+    utf8: Vec<u8>,
+    utf8_len: usize,
+}
+
+impl Parser {
+    fn feed(&mut self, bytes: &[u8], mut step: impl FnMut(Step)) {
+        for &b in bytes {
+            self.byte(b, &mut step);
+        }
+    }
+
+    fn byte(&mut self, b: u8, step: &mut impl FnMut(Step)) {
+        self.state = match self.state {
+            State::Text => {
+                if self.utf8_len > 0 {
+                    if b & 0xC0 == 0x80 {
+                        self.utf8.push(b);
+                        if self.utf8.len() == self.utf8_len {
+                            let width = std::str::from_utf8(&self.utf8)
+                                .ok()
+                                .and_then(|s| s.chars().next())
+                                .map_or(1, char_width);
+                            self.utf8.clear();
+                            self.utf8_len = 0;
+                            step(Step::Width(width));
+                        }
+                        return;
+                    }
+                    // A character cut short shows as a replacement character.
+                    step(self.take_partial());
+                }
+                match b {
+                    b'\x1B' => return self.state = State::Escape,
+                    b'\n' | b'\r' => step(Step::LineStart),
+                    b'\t' => step(Step::Tab),
+                    b'\x08' => step(Step::Backspace),
+                    0..=0x7F => step(Step::Width(char_width(char::from(b)))),
+                    0xC2..=0xF4 => {
+                        self.utf8.push(b);
+                        self.utf8_len = match b {
+                            0xC2..=0xDF => 2,
+                            0xE0..=0xEF => 3,
+                            _ => 4,
+                        };
+                    }
+                    _ => step(Step::Width(1)),
+                }
+                State::Text
+            }
+            State::Escape => match b {
+                b'[' => State::Csi,
+                b']' | b'P' | b'X' | b'^' | b'_' => State::String,
+                b' '..=b'/' => State::Intermediate,
+                _ => State::Text,
+            },
+            State::Csi if (0x40..=0x7E).contains(&b) => State::Text,
+            State::Csi => State::Csi,
+            State::String => match b {
+                b'\x07' => State::Text,
+                b'\x1B' => State::StringEscape,
+                _ => State::String,
+            },
+            State::StringEscape if b == b'\\' => State::Text,
+            State::StringEscape => State::String,
+            State::Intermediate if (b' '..=b'/').contains(&b) => State::Intermediate,
+            State::Intermediate => State::Text,
+        };
+    }
+
+    /// Drops the start of a character that was cut short, which shows as a replacement
+    /// character.
+    fn take_partial(&mut self) -> Step {
+        let width = if self.utf8.is_empty() { 0 } else { 1 };
+        self.utf8.clear();
+        self.utf8_len = 0;
+        Step::Width(width)
+    }
+}
+
+/// Returns the number of columns `bytes` takes up when written to a terminal.
+///
+/// Escape sequences (CSI sequences such as colors, OSC sequences such as hyperlinks, and other
+/// `ESC` sequences) and control characters take up no space, combining marks take up none either,
+/// and East Asian wide characters take up two columns. Invalid UTF-8 is counted as one column per
+/// replacement character.
+///
+/// This is meant for padding and truncating styled text; the widths of some characters (notably
+/// emoji) vary between terminals.
+///
+/// ```
+/// assert_eq!(term::display_width(b"\x1B[31mred\x1B[m"), 3);
+/// assert_eq!(term::display_width("日本".as_bytes()), 4);
+/// ```
+pub fn display_width(bytes: &[u8]) -> usize {
+    let mut parser = Parser::default();
+    let mut width = 0;
+    let mut add = |step| {
+        if let Step::Width(n) = step {
+            width += n;
+        }
+    };
+    parser.feed(bytes, &mut add);
+    add(parser.take_partial());
+    width
+}
+
+/// Tracks the column the cursor is in from the output written since the start of the line.
+///
+/// Like `display_width`, escape sequences take up no space, even when they're split between
+/// writes. Unlike it, tabs move to the next tab stop and backspaces move back a column.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ColumnTracker {
+    column: usize,
+    parser: Parser,
+}
+
+impl ColumnTracker {
+    /// Returns the current column, counting from 0.
+    pub(crate) fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Accounts for `bytes` being written, with tab stops every `tab_width` columns.
+    pub(crate) fn track(&mut self, bytes: &[u8], tab_width: usize) {
+        let column = &mut self.column;
+        self.parser.feed(bytes, |step| match step {
+            Step::Width(n) => *column += n,
+            Step::LineStart => *column = 0,
+            Step::Tab => *column += tab_width - *column % tab_width,
+            Step::Backspace => *column = column.saturating_sub(1),
+        });
+    }
+
+    /// Accounts for text `columns` wide being written by other means, such as a capability.
+    pub(crate) fn advance(&mut self, columns: usize) {
+        self.column += columns;
+    }
+
+    /// Accounts for the cursor being moved to the start of the line.
+    pub(crate) fn line_start(&mut self) {
+        self.column = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{display_width, ColumnTracker};
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width(b""), 0);
+        assert_eq!(display_width(b"plain"), 5);
+        assert_eq!(display_width(b"\x1B[1;38;5;200mhi\x1B[0m"), 2);
+        assert_eq!(display_width(b"\x1B]8;;http://a\x1B\\link\x1B]8;;\x07!"), 5);
+        assert_eq!(display_width(b"\x1B(Bx\x1B7y"), 2);
+        assert_eq!(display_width(b"a\tb\r\n"), 2);
+        assert_eq!(display_width("e\u{301}".as_bytes()), 1);
+        assert_eq!(display_width("ｈｉ한글".as_bytes()), 8);
+        assert_eq!(display_width(b"\xFFx"), 2);
+        // an unterminated sequence swallows the rest
+        assert_eq!(display_width(b"a\x1B[31"), 1);
+        assert_eq!(display_width(b"\xE6\x97x"), 2);
+    }
+
+    #[test]
+    fn test_column_tracker() {
+        let mut tracker = ColumnTracker::default();
+        // escape sequences and characters split between writes
+        for part in [
+            &b"ab\x1B["[..],
+            b"3",
+            b"1mc\x1B]8;;http",
+            b"://a\x1B",
+            b"\\d\xE6",
+            b"\x97\xA5",
+        ] {
+            tracker.track(part, 8);
+        }
+        assert_eq!(tracker.column(), 6);
+        tracker.track(b"x\ry", 8);
+        assert_eq!(tracker.column(), 1);
+        tracker.advance(3);
+        assert_eq!(tracker.column(), 4);
+        tracker.line_start();
+        assert_eq!(tracker.column(), 0);
+        // tabs go to the next tab stop, and backspaces stop at the start of the line
+        tracker.track(b"x\tz\x08\x08", 4);
+        assert_eq!(tracker.column(), 3);
+        tracker.track(b"\r\x08", 4);
+        assert_eq!(tracker.column(), 0);
+    }
+}
@@ -18,6 +18,7 @@ use crate::Error;
 use crate::Result;
 use crate::Terminal;
 
+use std::fmt;
 use std::io;
 use std::io::prelude::*;
 use std::ops::Deref;
@@ -37,6 +38,7 @@ use windows_sys::Win32::System::Console::{
 
 /// Console info which can be used by a Terminal implementation
 /// which uses the Win32 Console API.
+#[derive(Debug)]
 pub struct WinConsoleInfo {
     def_foreground: color::Color,
     def_background: color::Color,
@@ -49,8 +51,32 @@ pub struct WinConsoleInfo {
 
 /// A Terminal implementation which uses the Win32 Console API.
 pub struct WinConsole<T> {
+    // Declared before `saved` so that buffered text is written before the attributes are
+    // restored.
     buf: T,
     info: WinConsoleInfo,
+    restore_on_drop: bool,
+    saved: Option<SavedAttributes>,
+}
+
+/// The console's attributes from before a `WinConsole` first changed them, restored when dropped
+/// if `restore` is set.
+struct SavedAttributes {
+    attributes: CONSOLE_CHARACTER_ATTRIBUTES,
+    restore: bool,
+}
+
+impl Drop for SavedAttributes {
+    fn drop(&mut self) {
+        if !self.restore {
+            return;
+        }
+        if let Ok(out) = conout() {
+            unsafe {
+                SetConsoleTextAttribute(*out, self.attributes);
+            }
+        }
+    }
 }
 
 fn color_to_bits(color: color::Color) -> u16 {
@@ -75,6 +101,61 @@ fn color_to_bits(color: color::Color) -> u16 {
     }
 }
 
+/// The colors of the default console palette (Campbell), indexed by `color::Color`.
+const CONSOLE_PALETTE: [(u8, u8, u8); 16] = [
+    (12, 12, 12),
+    (197, 15, 31),
+    (19, 161, 14),
+    (193, 156, 0),
+    (0, 55, 218),
+    (136, 23, 152),
+    (58, 150, 221),
+    (204, 204, 204),
+    (118, 118, 118),
+    (231, 72, 86),
+    (22, 198, 12),
+    (249, 241, 165),
+    (59, 120, 255),
+    (180, 0, 158),
+    (97, 214, 214),
+    (242, 242, 242),
+];
+
+/// Returns the RGB value of the 256-color palette entry `color`, which must be from 16 to 255.
+fn palette_rgb(color: color::Color) -> (u8, u8, u8) {
+    if color >= 232 {
+        // The grayscale ramp
+        let level = (8 + (color - 232) * 10) as u8;
+        return (level, level, level);
+    }
+    // The 6x6x6 color cube
+    let level = |n: u32| if n == 0 { 0 } else { (55 + n * 40) as u8 };
+    let cube = color - 16;
+    (level(cube / 36), level(cube / 6 % 6), level(cube % 6))
+}
+
+/// Maps `color` to one of the 16 console colors, picking the nearest one for colors from the
+/// 256-color palette.
+///
+/// Returns `Err(Error::ColorOutOfRange)` for colors past the 256-color palette.
+fn console_color(color: color::Color) -> Result<color::Color> {
+    match color {
+        0..=15 => Ok(color),
+        16..=255 => {
+            let (r, g, b) = palette_rgb(color);
+            let distance = |&(cr, cg, cb): &(u8, u8, u8)| {
+                let d = |x: u8, y: u8| (i32::from(x) - i32::from(y)).pow(2);
+                d(r, cr) + d(g, cg) + d(b, cb)
+            };
+            let nearest = (0..CONSOLE_PALETTE.len())
+                .min_by_key(|&i| distance(&CONSOLE_PALETTE[i]))
+                .unwrap_or(0);
+            Ok(nearest as color::Color)
+        }
+        _ => Err(Error::ColorOutOfRange),
+    }
+}
+
 fn bits_to_color(bits: u16) -> color::Color {
     let color = match bits & 0x7 {
         0 => color::BLACK,
@@ -213,6 +294,14 @@ impl<T: Write + Send> WinConsole<T> {
         let out = conout()?;
         let _unused = self.buf.flush();
 
+        if self.saved.is_none() {
+            let buffer_info = unsafe { get_console_screen_buffer_info(*out)? };
+            self.saved = Some(SavedAttributes {
+                attributes: buffer_info.wAttributes,
+                restore: self.restore_on_drop,
+            });
+        }
+
         let (mut fg, bg) = if self.info.reverse {
             (self.info.background, self.info.foreground)
         } else {
@@ -242,7 +331,12 @@ impl<T: Write + Send> WinConsole<T> {
 
     /// Create a new WinConsole with the given WinConsoleInfo and out
     pub fn new_with_consoleinfo(out: T, info: WinConsoleInfo) -> WinConsole<T> {
-        WinConsole { buf: out, info }
+        WinConsole {
+            buf: out,
+            info,
+            restore_on_drop: false,
+            saved: None,
+        }
     }
 
     /// Returns `Err` whenever the terminal cannot be created for some
@@ -251,6 +345,31 @@ impl<T: Write + Send> WinConsole<T> {
         let info = WinConsoleInfo::from_env()?;
         Ok(Self::new_with_consoleinfo(out, info))
     }
+
+    /// Enables or disables restoring the console's attributes when the `WinConsole` is dropped.
+    ///
+    /// The console's attributes are shared by every handle to it, so when a program uses several
+    /// `WinConsole`s (say for stdout and stderr), `reset` on one of them restores the defaults it
+    /// captured when created, which may no longer be current. When enabled, dropping this
+    /// `WinConsole` (or calling `into_inner`) instead puts back the attributes that were in effect
+    /// before it first changed them, and does nothing if it never changed them. Disabled by
+    /// default.
+    pub fn set_restore_on_drop(&mut self, enabled: bool) {
+        self.restore_on_drop = enabled;
+        if let Some(saved) = &mut self.saved {
+            saved.restore = enabled;
+        }
+    }
+}
+
+impl<T> fmt::Debug for WinConsole<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WinConsole")
+            .field("num_colors", &16)
+            .field("info", &self.info)
+            .field("restore_on_drop", &self.restore_on_drop)
+            .finish_non_exhaustive()
+    }
 }
 
 impl<T: Write> Write for WinConsole<T> {
@@ -267,14 +386,22 @@ impl<T: Write + Send> Terminal for WinConsole<T> {
     type Output = T;
 
     fn fg(&mut self, color: color::Color) -> Result<()> {
-        self.info.foreground = color;
+        self.info.foreground = console_color(color)?;
         self.apply()?;
 
         Ok(())
     }
 
     fn bg(&mut self, color: color::Color) -> Result<()> {
-        self.info.background = color;
+        self.info.background = console_color(color)?;
+        self.apply()?;
+
+        Ok(())
+    }
+
+    fn set_colors(&mut self, fg: color::Color, bg: color::Color) -> Result<()> {
+        self.info.foreground = console_color(fg)?;
+        self.info.background = console_color(bg)?;
         self.apply()?;
 
         Ok(())
@@ -282,16 +409,8 @@ impl<T: Write + Send> Terminal for WinConsole<T> {
 
     fn attr(&mut self, attr: Attr) -> Result<()> {
         match attr {
-            Attr::ForegroundColor(f) => {
-                self.info.foreground = f;
-                self.apply()?;
-                Ok(())
-            }
-            Attr::BackgroundColor(b) => {
-                self.info.background = b;
-                self.apply()?;
-                Ok(())
-            }
+            Attr::ForegroundColor(f) => self.fg(f),
+            Attr::BackgroundColor(b) => self.bg(b),
             Attr::Reverse => {
                 self.info.reverse = true;
                 self.apply()?;
@@ -341,6 +460,11 @@ impl<T: Write + Send> Terminal for WinConsole<T> {
         true
     }
 
+    fn supports_color_index(&self, color: color::Color) -> bool {
+        // Colors from the 256-color palette are approximated, see `console_color`.
+        color < 256
+    }
+
     fn cursor_up(&mut self) -> Result<()> {
         let _unused = self.buf.flush();
         let handle = conout()?;
@@ -424,3 +548,22 @@ impl<T: Write + Send> Terminal for WinConsole<T> {
         self.buf
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::console_color;
+    use crate::color;
+
+    #[test]
+    fn test_console_color() {
+        assert_eq!(console_color(color::BRIGHT_RED).unwrap(), color::BRIGHT_RED);
+        // 196 is pure red, 21 pure blue, 46 pure green, 231 white and 232 near-black
+        assert_eq!(console_color(196).unwrap(), color::RED);
+        assert_eq!(console_color(21).unwrap(), color::BLUE);
+        assert_eq!(console_color(46).unwrap(), color::BRIGHT_GREEN);
+        assert_eq!(console_color(231).unwrap(), color::BRIGHT_WHITE);
+        assert_eq!(console_color(232).unwrap(), color::BLACK);
+        assert_eq!(console_color(244).unwrap(), color::BRIGHT_BLACK);
+        assert!(console_color(256).is_err());
+    }
+}
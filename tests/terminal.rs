@@ -0,0 +1,145 @@
+use std::io::{self, Write};
+use term::{color, Attr, Terminal};
+
+/// A terminal implemented outside the crate, which writes formatting as `<...>` tags.
+struct TagTerminal {
+    out: Vec<u8>,
+}
+
+impl TagTerminal {
+    fn tag(&mut self, tag: &str) -> term::Result<()> {
+        write!(self.out, "<{}>", tag)?;
+        Ok(())
+    }
+}
+
+impl Terminal for TagTerminal {
+    type Output = Vec<u8>;
+
+    fn fg(&mut self, color: color::Color) -> term::Result<()> {
+        self.tag(&format!("fg {}", color))
+    }
+
+    fn bg(&mut self, color: color::Color) -> term::Result<()> {
+        self.tag(&format!("bg {}", color))
+    }
+
+    fn attr(&mut self, attr: Attr) -> term::Result<()> {
+        self.tag(&format!("{:?}", attr))
+    }
+
+    fn supports_attr(&self, _attr: Attr) -> bool {
+        true
+    }
+
+    fn reset(&mut self) -> term::Result<()> {
+        self.tag("reset")
+    }
+
+    fn supports_reset(&self) -> bool {
+        true
+    }
+
+    fn supports_color(&self) -> bool {
+        true
+    }
+
+    fn supports_color_index(&self, color: color::Color) -> bool {
+        color < 256
+    }
+
+    fn cursor_up(&mut self) -> term::Result<()> {
+        Err(term::Error::NotSupported)
+    }
+
+    fn delete_line(&mut self) -> term::Result<()> {
+        Err(term::Error::NotSupported)
+    }
+
+    fn carriage_return(&mut self) -> term::Result<()> {
+        self.tag("cr")
+    }
+
+    fn begin_sync_update(&mut self) -> term::Result<()> {
+        Ok(())
+    }
+
+    fn end_sync_update(&mut self) -> term::Result<()> {
+        Ok(())
+    }
+
+    fn hyperlink(&mut self, url: &str, text: &str) -> term::Result<()> {
+        self.tag(&format!("a {}", url))?;
+        self.out.write_all(text.as_bytes())?;
+        self.tag("/a")
+    }
+
+    fn term_name(&self) -> Option<&str> {
+        Some("tags")
+    }
+
+    fn get_ref(&self) -> &Vec<u8> {
+        &self.out
+    }
+
+    fn get_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.out
+    }
+
+    fn into_inner(self) -> Vec<u8> {
+        self.out
+    }
+}
+
+impl Write for TagTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_external_impl() {
+    let mut term: Box<dyn Terminal<Output = Vec<u8>>> = Box::new(TagTerminal { out: Vec::new() });
+    term.write_styled("hi", Some(color::RED), None, &[Attr::Bold(true)])
+        .unwrap();
+    term.hyperlink("http://a", "a").unwrap();
+    // the default set_colors
+    term.set_colors(color::GREEN, color::BLUE).unwrap();
+    assert!(term.supports_bold());
+    assert_eq!(term.term_name(), Some("tags"));
+    assert_eq!(
+        term.get_ref(),
+        b"<fg 1><Bold(true)>hi<reset><a http://a>a</a><fg 2><bg 4>"
+    );
+}
+
+#[test]
+fn test_terminfo_cache() {
+    use std::sync::Arc;
+    use term::terminfo::Environment;
+    use term::TerminfoTerminal;
+
+    // No other test here creates a terminal from the environment, which would replace the cache.
+    let environment = Environment {
+        term: Some("dumb".into()),
+        ..Default::default()
+    };
+    let first = TerminfoTerminal::from_environment(Vec::new(), &environment).unwrap();
+    // only the variables that locate the entry matter
+    let columns = Environment {
+        columns: Some("100".into()),
+        ..environment.clone()
+    };
+    let second = TerminfoTerminal::from_environment(Vec::new(), &columns).unwrap();
+    assert!(Arc::ptr_eq(first.terminfo(), second.terminfo()));
+    assert_eq!(second.dims().0, 100);
+
+    term::clear_terminfo_cache();
+    let third = TerminfoTerminal::from_environment(Vec::new(), &environment).unwrap();
+    assert!(!Arc::ptr_eq(first.terminfo(), third.terminfo()));
+    assert_eq!(first.terminfo().names, third.terminfo().names);
+}
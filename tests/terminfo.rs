@@ -24,6 +24,1743 @@ fn test_supports_color() {
 
 #[test]
 fn test_fallback() {
-    TermInfo::from_name("ansi-cargo-test").expect("failed to use fallback");
+    let terminfo = TermInfo::from_name("ansi-cargo-test").expect("failed to use fallback");
+    assert_eq!(terminfo.names, ["ansi-cargo-test"]);
+    assert_eq!(terminfo.numbers["colors"], 8);
+    assert!(terminfo.strings.contains_key("cup"));
+    assert!(terminfo.strings.contains_key("sitm"));
+    let terminfo = TermInfo::from_name("xterm-256color-cargo-test").unwrap();
+    assert_eq!(terminfo.numbers["colors"], 256);
     assert!(TermInfo::from_name("really-bad-terminal").is_err());
 }
+
+#[test]
+fn test_parse_truncated() {
+    use term::terminfo::parser::compiled::parse;
+    for f in fs::read_dir("tests/data/").unwrap() {
+        let data = fs::read(f.unwrap().path()).unwrap();
+        for len in 0..data.len() {
+            // Must fail cleanly rather than panic. Broken extended sections are dropped, so
+            // some truncations still parse.
+            let _ = parse(&mut &data[..len], false);
+        }
+    }
+}
+
+#[test]
+fn test_parse_bad_string_offset() {
+    use term::terminfo::parser::compiled::parse;
+    use term::terminfo::Error;
+    let mut data = vec![0x1A, 0x01];
+    // names, bools, numbers, string offsets, string table
+    for n in [2u16, 0, 0, 1, 1] {
+        data.extend_from_slice(&n.to_le_bytes());
+    }
+    data.extend_from_slice(b"x\0");
+    // offset 5 points past the one-byte string table
+    data.extend_from_slice(&5u16.to_le_bytes());
+    data.push(0);
+    match parse(&mut &data[..], false) {
+        Err(term::Error::TerminfoParsing(Error::MalformedTerminfo(_))) => {}
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+    // a truncated string table is also rejected
+    data.pop();
+    assert!(matches!(
+        parse(&mut &data[..], false),
+        Err(term::Error::TerminfoParsing(Error::MalformedTerminfo(_)))
+    ));
+}
+
+#[test]
+fn test_parse_lenient() {
+    use term::terminfo::parser::compiled::{parse, parse_lenient};
+    use term::terminfo::Error;
+
+    let mut data = vec![0x1A, 0x01];
+    // names, bools, numbers, string offsets, string table
+    for n in [4u16, 0, 0, 2, 2] {
+        data.extend_from_slice(&n.to_le_bytes());
+    }
+    data.extend_from_slice(b"a\xFFb\0");
+    // cbt is fine, bel points past the end of the string table
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&5u16.to_le_bytes());
+    data.extend_from_slice(b"x\0");
+    assert!(parse(&mut &data[..], false).is_err());
+
+    let (terminfo, warnings) = parse_lenient(&mut &data[..], false).unwrap();
+    assert_eq!(terminfo.names, ["a\u{FFFD}b"]);
+    assert_eq!(terminfo.strings["cbt"], b"x");
+    assert!(!terminfo.strings.contains_key("bel"));
+    assert!(matches!(
+        warnings[..],
+        [Error::NotUtf8(_), Error::MalformedTerminfo(_)]
+    ));
+
+    // Truncated entries keep what's there.
+    let data = fs::read("tests/data/xterm").unwrap();
+    let (terminfo, warnings) = parse_lenient(&mut &data[..data.len() / 2], false).unwrap();
+    assert_eq!(terminfo.names[0], "xterm");
+    assert!(terminfo.bools["am"]);
+    assert!(!warnings.is_empty());
+    for len in 0..data.len() {
+        let _ = parse_lenient(&mut &data[..len], false);
+    }
+    let (_, warnings) = parse_lenient(&mut &data[..], false).unwrap();
+    assert_eq!(warnings, []);
+
+    // A broken extended section is dropped, keeping the standard capabilities.
+    let data = fs::read("tests/data/contour").unwrap();
+    let broken = &data[..data.len() - 1];
+    let terminfo = parse(&mut &broken[..], false).unwrap();
+    assert_eq!(terminfo.names[0], "contour");
+    assert!(terminfo.strings.contains_key("setaf"));
+    assert!(!terminfo.strings.contains_key("Ss"));
+    let (terminfo, warnings) = parse_lenient(&mut &broken[..], false).unwrap();
+    assert!(!terminfo.strings.contains_key("Ss"));
+    assert!(matches!(warnings[..], [Error::MalformedTerminfo(_)]));
+}
+
+#[test]
+fn test_supports_color_index() {
+    let terminfo = TermInfo::from_path("tests/data/linux").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert!(term.supports_color_index(term::color::RED));
+    // bright colors are dimmed on 8-color terminals
+    assert!(term.supports_color_index(term::color::BRIGHT_RED));
+    assert!(!term.supports_color_index(16));
+
+    let terminfo = TermInfo::from_path("tests/data/dumb").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert!(!term.supports_color_index(term::color::RED));
+}
+
+#[test]
+fn test_term_name() {
+    let terminfo = TermInfo::from_path("tests/data/linux").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert_eq!(term.term_name(), Some("linux"));
+
+    let terminfo = TermInfo::from_name("ansi-cargo-test").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert_eq!(term.term_name(), Some("ansi-cargo-test"));
+}
+
+#[test]
+fn test_dumb_terminal() {
+    use std::io::Write;
+    use term::DumbTerminal;
+
+    let mut term = DumbTerminal::new(Vec::new());
+    term.fg(term::color::RED).unwrap();
+    term.attr(term::Attr::Bold(true)).unwrap();
+    write!(term, "plain").unwrap();
+    term.reset().unwrap();
+    assert!(!term.supports_color());
+    assert_eq!(term.into_inner(), b"plain");
+}
+
+#[test]
+fn test_dumb_from_environment() {
+    use term::terminfo::Environment;
+
+    let environment = Environment {
+        term: Some("dumb".into()),
+        ..Default::default()
+    };
+    let terminfo = TermInfo::from_environment(&environment).unwrap();
+    assert_eq!(terminfo.names[0], "dumb");
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert!(!term.supports_color());
+    assert!(!term.supports_reset());
+}
+
+#[test]
+fn test_apply_cap_errors() {
+    let mut terminfo = TermInfo::from_name("ansi-cargo-test").unwrap();
+    terminfo.strings.insert("setab", b"\x1B[4%pA%dm".to_vec());
+    terminfo.strings.remove("bold");
+
+    let mut out = Vec::new();
+    assert_eq!(
+        terminfo.apply_cap("bold", &[], &mut out),
+        Err(term::Error::NotSupported)
+    );
+    assert_eq!(
+        terminfo.apply_cap("setab", &[], &mut out),
+        Err(term::Error::ParameterizedExpansion(
+            term::terminfo::parm::Error::InvalidParameterIndex('A')
+        ))
+    );
+
+    let mut term = TerminfoTerminal::new_with_terminfo(out, terminfo);
+    assert!(matches!(
+        term.bg(term::color::RED),
+        Err(term::Error::ParameterizedExpansion(_))
+    ));
+    assert_eq!(
+        term.attr(term::Attr::Bold(true)),
+        Err(term::Error::NotSupported)
+    );
+    assert!(term.get_ref().is_empty());
+}
+
+#[test]
+fn test_auto_margins() {
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert!(term.auto_margins());
+
+    let mut terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    terminfo.bools.remove("am");
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert!(!term.auto_margins());
+}
+
+#[test]
+fn test_sync_update() {
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.begin_sync_update().unwrap();
+    term.end_sync_update().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[?2026h\x1B[?2026l");
+
+    // contour isn't a known ANSI terminal, so this is its (extended) `Sync` capability.
+    let terminfo = TermInfo::from_path("tests/data/contour").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.begin_sync_update().unwrap();
+    term.end_sync_update().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[?2026h\x1B[?2026l");
+
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(term.begin_sync_update(), Err(term::Error::NotSupported));
+}
+
+#[test]
+fn test_buffered() {
+    use std::io::{BufWriter, Write};
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(BufWriter::new(Vec::new()), terminfo);
+    term.fg(term::color::RED).unwrap();
+    write!(term, "hi").unwrap();
+    assert!(term.get_ref().get_ref().is_empty());
+    term.flush().unwrap();
+    assert_eq!(term.get_ref().get_ref(), b"\x1B[31mhi");
+}
+
+#[test]
+fn test_legacy_colors() {
+    let mut terminfo = TermInfo::from_name("ansi-cargo-test").unwrap();
+    terminfo.strings.remove("setaf");
+    terminfo.strings.remove("setab");
+    terminfo.strings.insert("setf", b"<f%p1%d>".to_vec());
+    terminfo.strings.insert("setb", b"<b%p1%d>".to_vec());
+
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert!(term.supports_color());
+    term.fg(term::color::RED).unwrap();
+    term.bg(term::color::YELLOW).unwrap();
+    term.fg(term::color::GREEN).unwrap();
+    assert_eq!(term.get_ref(), b"<f4><b6><f2>");
+}
+
+#[test]
+fn test_max_color_pairs() {
+    let terminfo = TermInfo::from_path("tests/data/linux").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert_eq!(term.max_color_pairs(), 64);
+
+    let terminfo = TermInfo::from_path("tests/data/dumb").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert_eq!(term.max_color_pairs(), 0);
+}
+
+#[test]
+fn test_hyperlink_unsupported() {
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(
+        term.hyperlink("https://example.com", "example"),
+        Err(term::Error::NotSupported)
+    );
+    assert!(term.get_ref().is_empty());
+
+    let mut term = term::DumbTerminal::new(Vec::new());
+    assert_eq!(
+        term.hyperlink("https://example.com", "example"),
+        Err(term::Error::NotSupported)
+    );
+    assert!(term.get_ref().is_empty());
+}
+
+#[test]
+fn test_from_reader() {
+    let data = fs::read("tests/data/linux").unwrap();
+    let terminfo = TermInfo::from_reader(&mut &data[..]).unwrap();
+    assert_eq!(terminfo.names[0], "linux");
+}
+
+#[test]
+fn test_error_source() {
+    use std::error::Error as _;
+
+    let err = term::Error::from(io::Error::new(io::ErrorKind::Other, "boom"));
+    assert_eq!(err.source().unwrap().to_string(), "boom");
+
+    let err = term::Error::from(term::terminfo::Error::ShortNames);
+    assert!(err.source().is_some());
+    assert_eq!(err.to_string(), err.source().unwrap().to_string());
+
+    assert!(term::Error::TermUnset.source().is_none());
+    assert!(!term::Error::TermUnset.to_string().is_empty());
+}
+
+#[test]
+fn test_attr_fallbacks() {
+    use term::Attr;
+
+    let terminfo = TermInfo::from_path("tests/data/linux").unwrap();
+    assert!(!terminfo.strings.contains_key("sitm"));
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(
+        term.attr(Attr::Italic(true)),
+        Err(term::Error::NotSupported)
+    );
+
+    term.set_attr_fallbacks(true);
+    assert!(!term.supports_attr(Attr::Italic(true)));
+    term.attr(Attr::Italic(true)).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[4m");
+    assert_eq!(term.attr(Attr::Secure), Err(term::Error::NotSupported));
+}
+
+#[test]
+fn test_standout_fallback() {
+    use term::Attr;
+
+    let mut terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    terminfo.strings.remove("smso");
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert!(!term.supports_attr(Attr::Standout(true)));
+    assert_eq!(
+        term.attr(Attr::Standout(true)),
+        Err(term::Error::NotSupported)
+    );
+
+    term.set_attr_fallbacks(true);
+    assert!(!term.supports_attr(Attr::Standout(true)));
+    term.attr(Attr::Standout(true)).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[7m\x1B[1m");
+}
+
+#[test]
+fn test_query_background() {
+    fn query(reply: &[u8]) -> Option<(u8, u8, u8)> {
+        let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+        let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+        let color = term.query_background(&mut &reply[..]).unwrap();
+        assert_eq!(term.get_ref(), b"\x1B]11;?\x1B\\");
+        color
+    }
+    assert_eq!(
+        query(b"\x1B]11;rgb:ffff/8080/0000\x07"),
+        Some((0xff, 0x80, 0x00))
+    );
+    assert_eq!(query(b"\x1B]11;rgb:f/8/0\x1B\\"), Some((0xff, 0x88, 0x00)));
+    assert_eq!(query(b""), None);
+    assert_eq!(query(b"\x1B]11;rgb:ffff/8080\x07"), None);
+}
+
+#[test]
+fn test_set_default_colors() {
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_default_foreground(0xff, 0x80, 0x00).unwrap();
+    term.set_default_background(0, 0, 0).unwrap();
+    assert_eq!(
+        term.get_ref(),
+        b"\x1B]10;rgb:ff/80/00\x1B\\\x1B]11;rgb:00/00/00\x1B\\"
+    );
+}
+
+#[test]
+fn test_set_num_colors() {
+    let terminfo = TermInfo::from_path("tests/data/linux").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(term.num_colors(), 8);
+    assert_eq!(term.fg(200), Err(term::Error::ColorOutOfRange));
+
+    term.set_num_colors(256);
+    term.fg(term::color::RED).unwrap();
+    term.fg(term::color::BRIGHT_RED).unwrap();
+    term.bg(200).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[31m\x1B[38;5;9m\x1B[48;5;200m");
+}
+
+#[test]
+fn test_repeat_char() {
+    let mut terminfo = TermInfo::from_name("ansi-cargo-test").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    term.repeat_char(b'=', 3).unwrap();
+    assert_eq!(term.get_ref(), b"===");
+
+    terminfo
+        .strings
+        .insert("rep", b"%p1%c\x1B[%p2%{1}%-%db".to_vec());
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.repeat_char(b'=', 10).unwrap();
+    term.repeat_char(b'-', 1).unwrap();
+    assert_eq!(term.get_ref(), b"=\x1B[9b-");
+}
+
+#[test]
+fn test_back_color_erase() {
+    let terminfo = TermInfo::from_path("tests/data/linux").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert!(term.back_color_erase());
+
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert!(!term.back_color_erase());
+}
+
+#[test]
+fn test_full_reset() {
+    let mut terminfo = TermInfo::from_name("ansi-cargo-test").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    assert_eq!(term.full_reset(), Err(term::Error::NotSupported));
+
+    terminfo.strings.insert("is2", b"<is2>".to_vec());
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    term.full_reset().unwrap();
+    assert_eq!(term.get_ref(), b"<is2>");
+
+    terminfo.strings.insert("rs1", b"<rs1>".to_vec());
+    terminfo.strings.insert("rs3", b"<rs3>".to_vec());
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.full_reset().unwrap();
+    assert_eq!(term.get_ref(), b"<rs1><rs3>");
+}
+
+#[test]
+fn test_keypad_mode() {
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.keypad_xmit().unwrap();
+    term.keypad_local().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[?1h\x1B=\x1B[?1l\x1B>");
+
+    let terminfo = TermInfo::from_path("tests/data/dumb").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(term.keypad_xmit(), Err(term::Error::NotSupported));
+}
+
+#[test]
+fn test_key_sequence() {
+    use term::terminfo::Key;
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert_eq!(term.key_sequence(Key::Up), Some(&b"\x1BOA"[..]));
+    assert_eq!(term.key_sequence(Key::F(1)), Some(&b"\x1BOP"[..]));
+    assert_eq!(term.key_sequence(Key::F(64)), None);
+}
+
+#[test]
+fn test_read_key() {
+    use term::terminfo::Key;
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    let mut input: &[u8] =
+        b"a\xC3\xA9\r\t\x7F\x03\x1BOA\x1B[A\x1B[15~\x1B[[A\x1Bx\x1B[1;5Xb\x1B\x1B\x1B\xC3\xA9\x1B";
+    let mut keys = Vec::new();
+    while let Some(key) = term.read_key(&mut input).unwrap() {
+        keys.push(key);
+    }
+    assert_eq!(
+        keys,
+        [
+            Key::Char('a'),
+            Key::Char('\u{e9}'),
+            Key::Enter,
+            Key::Tab,
+            Key::Backspace,
+            Key::Ctrl('c'),
+            Key::Up,
+            Key::Up,
+            Key::F(5),
+            Key::F(1),
+            Key::Alt('x'),
+            Key::Char('b'),
+            Key::Alt('\x1B'),
+            Key::Alt('\u{e9}'),
+            Key::Escape,
+        ]
+    );
+    assert_eq!(term.key_sequence(Key::Char('a')), None);
+}
+
+#[test]
+fn test_diff() {
+    let linux = TermInfo::from_path("tests/data/linux").unwrap();
+    assert!(linux.diff(&linux).is_empty());
+
+    let mut other = linux.clone();
+    other.strings.remove("bold");
+    other.numbers.insert("colors", 256);
+    other.strings.insert("sitm", b"\x1B[3m".to_vec());
+    other.bools.insert("bce", false);
+
+    let diff = linux.diff(&other);
+    assert_eq!(diff.only_in_self, ["bold"]);
+    assert_eq!(diff.only_in_other, ["sitm"]);
+    assert_eq!(diff.differing, ["bce", "colors"]);
+}
+
+#[test]
+fn test_shared_terminfo() {
+    use std::sync::Arc;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<TermInfo>();
+
+    let terminfo = Arc::new(TermInfo::from_path("tests/data/linux").unwrap());
+    let handles: Vec<_> = (0..2)
+        .map(|_| {
+            let terminfo = terminfo.clone();
+            std::thread::spawn(move || {
+                let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+                term.fg(term::color::RED).unwrap();
+                term.into_inner()
+            })
+        })
+        .collect();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), b"\x1B[31m");
+    }
+}
+
+#[test]
+fn test_with_output() {
+    use std::sync::Arc;
+
+    let terminfo = TermInfo::from_path("tests/data/linux").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_num_colors(256);
+    let mut other = term.with_output(Vec::new());
+    assert!(Arc::ptr_eq(term.terminfo(), other.terminfo()));
+    other.fg(200).unwrap();
+    assert_eq!(other.get_ref(), b"\x1B[38;5;200m");
+    assert!(term.get_ref().is_empty());
+}
+
+#[test]
+fn test_supports_attr_helpers() {
+    let terminfo = TermInfo::from_path("tests/data/linux").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    assert!(term.supports_bold());
+    assert!(term.supports_underline());
+    assert!(!term.supports_italic());
+}
+
+#[test]
+fn test_copy_to_clipboard() {
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    for (data, encoded) in [
+        (&b""[..], &b""[..]),
+        (b"f", b"Zg=="),
+        (b"fo", b"Zm8="),
+        (b"foo", b"Zm9v"),
+        (b"foobar", b"Zm9vYmFy"),
+    ] {
+        term.get_mut().clear();
+        term.copy_to_clipboard(data).unwrap();
+        let mut expected = b"\x1B]52;c;".to_vec();
+        expected.extend_from_slice(encoded);
+        expected.push(b'\x07');
+        assert_eq!(term.get_ref(), &expected);
+    }
+
+    // Ms is used when the entry declares it
+    let terminfo = TermInfo::from_path("tests/data/xterm-truecolor").unwrap();
+    assert!(terminfo.strings.contains_key("Ms"));
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.copy_to_clipboard(b"foo").unwrap();
+    assert_eq!(term.get_ref(), b"\x1B]52;c;Zm9v\x07");
+
+    let mut terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    assert_eq!(
+        term.copy_to_clipboard(b"foo"),
+        Err(term::Error::NotSupported)
+    );
+    terminfo
+        .strings
+        .insert("Ms", b"\x1B]52;%p1%s;%p2%s\x1B\\".to_vec());
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.copy_to_clipboard(b"foo").unwrap();
+    assert_eq!(term.get_ref(), b"\x1B]52;c;Zm9v\x1B\\");
+}
+
+#[test]
+fn test_color_choice_never() {
+    let term = term::stdout_with_choice(term::ColorChoice::Never);
+    assert!(!term.supports_color());
+    assert_eq!(term.term_name(), None);
+}
+
+#[test]
+fn test_dims() {
+    use term::terminfo::Environment;
+
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    let environment = Environment {
+        columns: Some("132".into()),
+        lines: Some("lots".into()),
+        ..Default::default()
+    };
+    term.set_environment(environment.clone());
+    assert_eq!(term.dims(), (132, 24));
+
+    // Without bce, fill_line_bg pads to the width from dims.
+    let mut terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    terminfo.bools.remove("bce");
+    terminfo.strings.remove("rep");
+    let mut filled = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    filled.set_environment(environment);
+    filled.fill_line_bg("item", term::color::BLUE).unwrap();
+    let mut expected = b"\x1B[44mitem".to_vec();
+    expected.resize(expected.len() + 128, b' ');
+    expected.extend_from_slice(b"\x1B(B\x1B[m");
+    assert_eq!(filled.get_ref(), &expected);
+
+    term.set_environment(Environment::default());
+    assert_eq!(term.dims(), (80, 24));
+
+    let mut terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    terminfo.numbers.clear();
+    let mut term = TerminfoTerminal::new_with_terminfo(io::sink(), terminfo);
+    term.set_environment(Environment::default());
+    assert_eq!(term.dims(), (80, 24));
+}
+
+#[test]
+fn test_cursor_shape() {
+    use term::terminfo::CursorShape;
+
+    // xterm's entry has the (extended) `Ss` and `Se` capabilities; `Se` restores a steady block.
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_cursor_shape(CursorShape::SteadyBar).unwrap();
+    term.reset_cursor_shape().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[6 q\x1B[2 q");
+
+    // contour isn't a known ANSI terminal, so only its capabilities can be used.
+    let terminfo = TermInfo::from_path("tests/data/contour").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_cursor_shape(CursorShape::BlinkingUnderline)
+        .unwrap();
+    term.reset_cursor_shape().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[3 q\x1B[ q");
+
+    // Without the capabilities, ANSI terminals get DECSCUSR.
+    let mut terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    terminfo.strings.remove("Ss");
+    terminfo.strings.remove("Se");
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_cursor_shape(CursorShape::SteadyBar).unwrap();
+    term.reset_cursor_shape().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[6 q\x1B[0 q");
+
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(
+        term.set_cursor_shape(CursorShape::SteadyBlock),
+        Err(term::Error::NotSupported)
+    );
+}
+
+#[test]
+fn test_parse_names_not_utf8() {
+    use term::terminfo::parser::compiled::parse;
+    use term::terminfo::Error;
+
+    let mut data = vec![0x1A, 0x01];
+    // names, bools, numbers, string offsets, string table
+    for n in [4u16, 0, 0, 0, 0] {
+        data.extend_from_slice(&n.to_le_bytes());
+    }
+    data.extend_from_slice(b"a\xFFb\0");
+    assert!(matches!(
+        parse(&mut &data[..], false),
+        Err(term::Error::TerminfoParsing(Error::NotUtf8(_)))
+    ));
+}
+
+#[test]
+fn test_attr_off() {
+    use term::{color, Attr};
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert!(term.supports_attr(Attr::Bold(false)));
+    term.attr(Attr::Bold(true)).unwrap();
+    term.attr(Attr::Bold(false)).unwrap();
+    term.attr(Attr::Dim(false)).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[1m\x1B[22m\x1B[22m");
+
+    // `sgr` sets the other attributes and the colors again
+    let terminfo = TermInfo::from_path("tests/data/contour").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert!(term.supports_attr(Attr::Bold(false)));
+    term.reset().unwrap();
+    term.attr(Attr::Underline(true)).unwrap();
+    term.attr(Attr::Italic(true)).unwrap();
+    term.fg(color::RED).unwrap();
+    term.bg(color::BLUE).unwrap();
+    term.attr(Attr::Bold(true)).unwrap();
+    term.take_buffer();
+    term.attr(Attr::Bold(false)).unwrap();
+    term.attr(Attr::Dim(false)).unwrap();
+    term.fg(color::RED).unwrap();
+    assert_eq!(
+        term.take_buffer(),
+        b"\x1B(B\x1B[0;4m\x1B[3m\x1B[31m\x1B[44m"
+    );
+
+    let mut terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    terminfo.strings.remove("sgr");
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert!(!term.supports_attr(Attr::Bold(false)));
+    assert_eq!(term.attr(Attr::Bold(false)), Err(term::Error::NotSupported));
+    // even when the style cache knows bold is off
+    term.reset().unwrap();
+    assert_eq!(term.attr(Attr::Bold(false)), Err(term::Error::NotSupported));
+    assert_eq!(term.attr(Attr::Dim(false)), Err(term::Error::NotSupported));
+}
+
+#[test]
+fn test_protected() {
+    use term::Attr;
+
+    let mut terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    assert!(!term.supports_attr(Attr::Protected(true)));
+    assert_eq!(
+        term.attr(Attr::Protected(true)),
+        Err(term::Error::NotSupported)
+    );
+    assert!(term.supports_attr(Attr::Protected(false)));
+    term.attr(Attr::Protected(false)).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[0\"q");
+
+    terminfo.strings.insert("prot", b"\x1B[1\"q".to_vec());
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert!(term.supports_attr(Attr::Protected(true)));
+    term.attr(Attr::Protected(true)).unwrap();
+    term.attr(Attr::Protected(false)).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[1\"q\x1B[0\"q");
+}
+
+#[test]
+fn test_query() {
+    fn query(reply: &[u8]) -> Option<Vec<u32>> {
+        let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+        let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+        let numbers = term.query(&mut &reply[..], "u7", "u6").unwrap();
+        assert_eq!(term.get_ref(), b"\x1B[6n");
+        numbers
+    }
+    assert_eq!(query(b"\x1B[12;40R"), Some(vec![11, 39]));
+    assert_eq!(query(b"\x1B[12;40"), None);
+    assert_eq!(query(b"\x1B[12:40R"), None);
+    assert_eq!(query(b""), None);
+}
+
+#[test]
+fn test_has_caps() {
+    let terminfo = TermInfo::from_path("tests/data/linux").unwrap();
+    assert_eq!(
+        terminfo.has_caps(["bold", "sitm", "colors", "bce", "xsb"]),
+        [true, false, true, true, false]
+    );
+}
+
+#[test]
+fn test_bool_accessors() {
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    assert!(terminfo.auto_margins());
+    assert!(terminfo.back_color_erase());
+    assert!(terminfo.move_standout_mode());
+    assert!(!terminfo.has_status_line());
+
+    let mut terminfo = terminfo;
+    terminfo.bools.insert("hs", true);
+    terminfo.bools.insert("msgr", false);
+    assert!(terminfo.has_status_line());
+    assert!(!terminfo.move_standout_mode());
+}
+
+#[test]
+fn test_ansi() {
+    use std::io::Write;
+
+    let mut term = TerminfoTerminal::ansi(Vec::new());
+    assert_eq!(term.term_name(), Some("xterm-256color"));
+    assert_eq!(term.num_colors(), 256);
+    term.fg(term::color::RED).unwrap();
+    term.bg(term::color::BRIGHT_BLUE).unwrap();
+    term.attr(term::Attr::Italic(true)).unwrap();
+    write!(term, "hi").unwrap();
+    term.fg(200).unwrap();
+    term.reset().unwrap();
+    assert_eq!(
+        term.get_ref(),
+        b"\x1B[31m\x1B[104m\x1B[3mhi\x1B[38;5;200m\x1B[0m"
+    );
+}
+
+#[test]
+fn test_builtin_entries() {
+    let msys = TerminfoTerminal::new_with_terminfo(Vec::new(), TermInfo::msys());
+    assert_eq!(msys.term_name(), Some("msyscon"));
+    assert_eq!(msys.num_colors(), 8);
+
+    let dumb = TerminfoTerminal::new_with_terminfo(Vec::new(), TermInfo::dumb());
+    assert_eq!(dumb.term_name(), Some("dumb"));
+    assert!(!dumb.supports_color());
+}
+
+#[test]
+fn test_take_buffer() {
+    use std::io::Write;
+
+    let mut term = TerminfoTerminal::ansi(Vec::new());
+    term.fg(term::color::RED).unwrap();
+    write!(term, "hi").unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B[31mhi");
+    assert!(term.get_ref().is_empty());
+    term.reset().unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B[0m");
+}
+
+#[test]
+fn test_parse_large() {
+    use term::terminfo::parser::compiled::parse;
+
+    let terminfo = TermInfo::from_path("tests/data/large").unwrap();
+    assert_eq!(terminfo.strings["bel"], [b'a'; 1550]);
+    assert_eq!(terminfo.strings["civis"], [b't'; 1550]);
+
+    // A string table over 32KiB, with offsets past 0x7FFF.
+    let mut data = vec![0x1A, 0x01];
+    // names, bools, numbers, string offsets, string table
+    for n in [2u16, 0, 0, 2, 0x9000] {
+        data.extend_from_slice(&n.to_le_bytes());
+    }
+    data.extend_from_slice(b"x\0");
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&0x8FFCu16.to_le_bytes());
+    let mut table = vec![b'a'; 0x9000];
+    table[0x8FFB] = 0;
+    table[0x8FFF] = 0;
+    data.extend_from_slice(&table);
+    let terminfo = parse(&mut &data[..], false).unwrap();
+    assert_eq!(terminfo.strings["cbt"].len(), 0x8FFB);
+    assert_eq!(terminfo.strings["bel"], b"aaa");
+}
+
+#[test]
+fn test_reset_fg_bg() {
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.reset_fg().unwrap();
+    term.reset_bg().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[39m\x1B[49m");
+
+    let mut terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    terminfo.names = vec!["fancy".to_owned()];
+    terminfo.strings.insert("op", b"<op>".to_vec());
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    term.reset_fg().unwrap();
+    term.reset_bg().unwrap();
+    assert_eq!(term.get_ref(), b"<op><op>");
+
+    terminfo.strings.remove("op");
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(term.reset_fg(), Err(term::Error::NotSupported));
+    assert!(term.get_ref().is_empty());
+}
+
+#[test]
+fn test_multiplexer() {
+    use term::terminfo::{Environment, Multiplexer};
+
+    let mut screen = TerminfoTerminal::new_with_terminfo(
+        io::sink(),
+        TermInfo::from_path("tests/data/screen-256color").unwrap(),
+    );
+    let mut xterm = TerminfoTerminal::new_with_terminfo(
+        io::sink(),
+        TermInfo::from_path("tests/data/xterm").unwrap(),
+    );
+    screen.set_environment(Environment::default());
+    xterm.set_environment(Environment::default());
+    assert_eq!(screen.multiplexer(), Some(Multiplexer::Screen));
+    assert_eq!(xterm.multiplexer(), None);
+
+    let environment = Environment {
+        sty: Some("1234.pts-0.host".into()),
+        ..Default::default()
+    };
+    xterm.set_environment(environment.clone());
+    assert_eq!(xterm.multiplexer(), Some(Multiplexer::Screen));
+    let environment = Environment {
+        tmux: Some("/tmp/tmux-1000/default,1234,0".into()),
+        ..environment
+    };
+    xterm.set_environment(environment.clone());
+    screen.set_environment(environment);
+    assert_eq!(xterm.multiplexer(), Some(Multiplexer::Tmux));
+    assert_eq!(screen.multiplexer(), Some(Multiplexer::Tmux));
+
+    let mut term = xterm.with_output(Vec::new());
+    term.copy_to_clipboard(b"hi").unwrap();
+    assert_eq!(term.get_ref(), b"\x1B]52;c;aGk=\x07");
+    term.get_mut().clear();
+    term.set_tmux_passthrough(true);
+    term.copy_to_clipboard(b"hi").unwrap();
+    term.hyperlink("http://a", "a").unwrap();
+    assert_eq!(
+        term.get_ref(),
+        b"\x1BPtmux;\x1B\x1B]52;c;aGk=\x07\x1B\\\
+          \x1BPtmux;\x1B\x1B]8;;http://a\x1B\x1B\\\x1B\\a\
+          \x1BPtmux;\x1B\x1B]8;;\x1B\x1B\\\x1B\\"
+    );
+}
+
+#[test]
+fn test_reset_palette() {
+    let terminfo = TermInfo::from_path("tests/data/linux").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.reset_palette().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B]R");
+
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(term.reset_palette(), Err(term::Error::NotSupported));
+}
+
+#[test]
+fn test_set_palette_color() {
+    let terminfo = TermInfo::from_path("tests/data/xterm-256color").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_palette_color(200, 0x80, 0xFF, 0x01).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B]4;200;rgb:80/FF/01\x1B\\");
+    assert_eq!(
+        term.set_palette_color(256, 0, 0, 0),
+        Err(term::Error::ColorOutOfRange)
+    );
+
+    let mut terminfo = TermInfo::from_path("tests/data/xterm-256color").unwrap();
+    terminfo.strings.remove("initc");
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_palette_color(1, 0x80, 0xFF, 0x01).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B]4;1;rgb:80/ff/01\x1B\\");
+
+    let terminfo = TermInfo::from_path("tests/data/linux").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_palette_color(1, 0x12, 0x34, 0x56).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B]P1123456");
+}
+
+#[test]
+fn test_extended_strings() {
+    // Compiled with `tic -x` from tests/source/contour.
+    let terminfo = TermInfo::from_path("tests/data/contour").unwrap();
+    let cap = |name: &str| terminfo.strings.get(name).map(|s| &s[..]);
+    assert_eq!(cap("Ss"), Some(&b"\x1B[%p1%d q"[..]));
+    assert_eq!(cap("Se"), Some(&b"\x1B[ q"[..]));
+    assert_eq!(cap("Sync"), Some(&b"\x1B[?2026%?%p1%{1}%-%tl%eh%;"[..]));
+    // extended capabilities this crate doesn't use are skipped
+    assert!(!terminfo.strings.contains_key("Smulx"));
+}
+
+#[test]
+fn test_from_source() {
+    for name in ["contour", "linux", "xterm-256color"] {
+        let source = fs::read_to_string(format!("tests/source/{}", name)).unwrap();
+        let parsed = TermInfo::from_source(&source).unwrap();
+        let compiled = TermInfo::from_path(format!("tests/data/{}", name)).unwrap();
+        assert_eq!(parsed.names, compiled.names);
+        assert_eq!(parsed.bools, compiled.bools);
+        assert_eq!(parsed.numbers, compiled.numbers);
+        assert_eq!(parsed.strings, compiled.strings);
+    }
+
+    assert!(TermInfo::from_source("# nothing here\n").is_err());
+    assert!(TermInfo::from_source("x|y,\n\tuse=xterm,\n").is_err());
+    assert!(TermInfo::from_source("x|y,\n\tcols#lots,\n").is_err());
+}
+
+#[test]
+fn test_partial_colors() {
+    use term::Attr;
+
+    let mut terminfo = TermInfo::from_name("ansi-cargo-test").unwrap();
+    terminfo.strings.remove("setab");
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    assert!(term.supports_color());
+    assert!(term.supports_attr(Attr::ForegroundColor(term::color::RED)));
+    assert!(!term.supports_attr(Attr::BackgroundColor(term::color::RED)));
+    term.fg(term::color::RED).unwrap();
+    assert_eq!(term.bg(term::color::RED), Err(term::Error::NotSupported));
+    assert_eq!(term.get_ref(), b"\x1B[31m");
+
+    terminfo.strings.remove("setaf");
+    terminfo.strings.insert("setb", b"<b%p1%d>".to_vec());
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(term.fg(term::color::RED), Err(term::Error::NotSupported));
+    term.bg(term::color::RED).unwrap();
+    assert_eq!(term.get_ref(), b"<b4>");
+}
+
+#[test]
+fn test_cursor_n() {
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.cursor_up_n(3).unwrap();
+    term.cursor_down_n(2).unwrap();
+    term.cursor_left_n(1).unwrap();
+    term.cursor_right_n(0).unwrap();
+    term.cursor_right_n(10).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[3A\x1B[2B\x08\x1B[10C");
+
+    let mut terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    terminfo.strings.remove("cuu");
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    term.cursor_up_n(3).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[A\x1B[A\x1B[A");
+
+    terminfo.strings.remove("cuu1");
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(term.cursor_up_n(3), Err(term::Error::NotSupported));
+    assert!(term.get_ref().is_empty());
+}
+
+#[test]
+fn test_next_prev_line() {
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.next_line(0).unwrap();
+    term.next_line(1).unwrap();
+    term.next_line(3).unwrap();
+    term.prev_line(0).unwrap();
+    term.prev_line(2).unwrap();
+    assert_eq!(term.get_ref(), b"\r\n\x1B[3E\x1B[2F");
+
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    term.next_line(2).unwrap();
+    term.prev_line(1).unwrap();
+    assert_eq!(term.get_ref(), b"\r\x1B[2B\r\x1B[A");
+
+    let mut terminfo = terminfo;
+    terminfo.strings.insert("nel", b"\x1BE".to_vec());
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    term.next_line(1).unwrap();
+    assert_eq!(term.get_ref(), b"\x1BE");
+
+    terminfo.strings.remove("nel");
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.next_line(1).unwrap();
+    assert_eq!(term.get_ref(), b"\r\n");
+}
+
+#[test]
+fn test_set_progress() {
+    use term::terminfo::ProgressState;
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_progress(ProgressState::Normal, 42).unwrap();
+    term.set_progress(ProgressState::Error, 200).unwrap();
+    term.set_progress(ProgressState::None, 0).unwrap();
+    assert_eq!(
+        term.get_ref(),
+        b"\x1B]9;4;1;42\x07\x1B]9;4;2;100\x07\x1B]9;4;0;0\x07"
+    );
+
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(
+        term.set_progress(ProgressState::Indeterminate, 0),
+        Err(term::Error::NotSupported)
+    );
+}
+
+#[test]
+fn test_hard_reset() {
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.hard_reset().unwrap();
+    assert_eq!(term.get_ref(), b"\x1Bc");
+
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(term.hard_reset(), Err(term::Error::NotSupported));
+}
+
+#[test]
+fn test_keyboard_enhancement() {
+    use term::terminfo::keyboard;
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.push_keyboard_enhancement(
+        keyboard::DISAMBIGUATE_ESCAPE_CODES | keyboard::REPORT_EVENT_TYPES,
+    )
+    .unwrap();
+    term.pop_keyboard_enhancement().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[>3u\x1B[<u");
+
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(
+        term.push_keyboard_enhancement(keyboard::DISAMBIGUATE_ESCAPE_CODES),
+        Err(term::Error::NotSupported)
+    );
+}
+
+#[test]
+fn test_supports_sixel() {
+    use term::terminfo::Environment;
+
+    fn sixel_in(name: &str, reply: &[u8], environment: Environment) -> bool {
+        let mut terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+        terminfo.names = vec![name.to_owned()];
+        let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+        term.set_environment(environment);
+        let sixel = term.supports_sixel(&mut &reply[..]).unwrap();
+        assert_eq!(term.get_ref(), b"\x1B[c");
+        sixel
+    }
+    fn sixel(name: &str, reply: &[u8]) -> bool {
+        sixel_in(name, reply, Environment::default())
+    }
+    assert!(sixel("xterm", b"\x1B[?63;1;2;4;6;9;15;22c"));
+    assert!(!sixel("xterm", b"\x1B[?64;1;2;6;9;15;18;21;22c"));
+    // the class isn't an attribute
+    assert!(!sixel("xterm", b"\x1B[?4;6c"));
+    assert!(!sixel("xterm", b""));
+    assert!(!sixel("xterm", b"\x1B[?6x4c"));
+    assert!(sixel("foot", b""));
+    assert!(!sixel("foot", b"\x1B[?62;22c"));
+    assert!(sixel("mlterm", b""));
+    let wezterm = Environment {
+        term_program: Some("WezTerm".into()),
+        ..Default::default()
+    };
+    assert!(sixel_in("xterm-256color", b"", wezterm));
+
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(
+        term.supports_sixel(&mut &b""[..]),
+        Err(term::Error::NotSupported)
+    );
+}
+
+#[test]
+fn test_fg_reset() {
+    use std::io::Write;
+
+    let terminfo = TermInfo::from_path("tests/data/xterm-256color").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.fg(term::color::GREEN).unwrap();
+    write!(term, "ok").unwrap();
+    term.reset().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[32mok\x1B(B\x1B[m");
+}
+
+#[test]
+fn test_number_accessors() {
+    let terminfo = TermInfo::from_path("tests/data/xterm-256color").unwrap();
+    assert_eq!(terminfo.colors(), 256);
+    assert_eq!(terminfo.columns(), Some(80));
+    assert_eq!(terminfo.lines(), Some(24));
+    assert_eq!(terminfo.max_pairs(), Some(65536));
+
+    let terminfo = TermInfo::from_path("tests/data/dumb").unwrap();
+    assert_eq!(terminfo.colors(), 0);
+    assert_eq!(terminfo.columns(), Some(80));
+    assert_eq!(terminfo.lines(), None);
+    assert_eq!(terminfo.max_pairs(), None);
+}
+
+#[test]
+fn test_terminfo_file() {
+    use std::path::Path;
+    use term::terminfo::searcher::get_dbpath_for_term_in;
+    use term::terminfo::Environment;
+
+    // TERMINFO names the entry itself rather than a database.
+    let environment = Environment {
+        term: Some("vt100".into()),
+        terminfo: Some("tests/data/vt100".into()),
+        ..Default::default()
+    };
+    assert_eq!(
+        get_dbpath_for_term_in("vt100", &environment).as_deref(),
+        Some(Path::new("tests/data/vt100"))
+    );
+    assert_eq!(
+        get_dbpath_for_term_in("searcher-cargo-test", &environment),
+        None
+    );
+    let terminfo = TermInfo::from_environment(&environment).unwrap();
+    assert_eq!(terminfo.names[0], "vt100");
+}
+
+#[test]
+fn test_invalid_term_names() {
+    use term::terminfo::searcher::get_dbpath_for_term;
+
+    for name in [
+        "",
+        ".",
+        "..",
+        "../x/xterm",
+        "x/../../x/xterm",
+        "/usr/share/terminfo/x/xterm",
+        "..\\x\\xterm",
+        "xterm\0",
+    ] {
+        assert_eq!(get_dbpath_for_term(name), None, "{:?}", name);
+    }
+    assert!(matches!(
+        TermInfo::from_name("../../tests/data/xterm"),
+        Err(term::Error::TerminfoEntryNotFound)
+    ));
+}
+
+#[test]
+fn test_from_environment() {
+    use term::terminfo::Environment;
+
+    let env = Environment {
+        term: Some("xterm".into()),
+        terminfo: Some("tests/data/xterm".into()),
+        ..Environment::default()
+    };
+    assert_eq!(TermInfo::from_environment(&env).unwrap().names[0], "xterm");
+
+    let env = Environment {
+        term: Some("vt100".into()),
+        terminfo_dirs: Some("tests/data/vt100".into()),
+        ..Environment::default()
+    };
+    assert_eq!(TermInfo::from_environment(&env).unwrap().names[0], "vt100");
+
+    assert!(matches!(
+        TermInfo::from_environment(&Environment::default()),
+        Err(term::Error::TermUnset)
+    ));
+}
+
+#[test]
+fn test_fill_line_bg() {
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.fill_line_bg("item", term::color::BLUE).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[44mitem\x1B[K\x1B(B\x1B[m");
+}
+
+#[test]
+fn test_truecolor() {
+    fn terminal(name: &str) -> TerminfoTerminal<io::Sink> {
+        let terminfo = TermInfo::from_path(format!("tests/data/{}", name)).unwrap();
+        TerminfoTerminal::new_with_terminfo(io::sink(), terminfo)
+    }
+
+    let term = terminal("xterm-truecolor");
+    assert!(term.truecolor());
+    assert_eq!(
+        term.terminfo().strings.get("setrgbf").map(|s| &s[..]),
+        Some(&b"\x1B[38;2;%p1%d;%p2%d;%p3%dm"[..])
+    );
+    // the standard capabilities are still read
+    assert_eq!(term.num_colors(), 256);
+    assert!(term.back_color_erase());
+
+    let term = terminal("xterm-direct");
+    assert!(term.truecolor());
+    assert_eq!(term.num_colors(), 0x1000000);
+
+    assert!(!terminal("xterm-256color").truecolor());
+    assert!(!terminal("linux").truecolor());
+
+    let terminfo = TermInfo::from_source("t|test, Tc, colors#8, setaf=\\E[3%p1%dm,").unwrap();
+    assert!(TerminfoTerminal::new_with_terminfo(io::sink(), terminfo).truecolor());
+}
+
+#[test]
+fn test_debug() {
+    let terminfo = TermInfo::from_path("tests/data/xterm-256color").unwrap();
+    let term = TerminfoTerminal::new_with_terminfo(Vec::<u8>::new(), terminfo);
+    assert_eq!(
+        format!("{:?}", term),
+        r#"TerminfoTerminal { name: Some("xterm-256color"), num_colors: 256, truecolor: false, .. }"#
+    );
+
+    let boxed: Box<dyn Terminal<Output = Vec<u8>> + Send> = Box::new(term);
+    assert_eq!(
+        format!("{:?}", boxed),
+        r#"Terminal { name: Some("xterm-256color"), supports_color: true, .. }"#
+    );
+}
+
+#[test]
+fn test_expand_tabs() {
+    use std::io::Write;
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    assert_eq!(term.tab_width(), 8);
+    term.expand_tabs("a\tbc\td\n\tx").unwrap();
+    assert_eq!(term.get_ref(), b"a       bc      d\n        x");
+
+    // colors and wide characters are accounted for
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    term.fg(term::color::RED).unwrap();
+    write!(term, "日本").unwrap();
+    term.reset().unwrap();
+    term.expand_tabs("\t|").unwrap();
+    assert!(term.get_ref().ends_with("日本\x1B(B\x1B[m    |".as_bytes()));
+
+    // so are escape sequences split between writes, and text written by other methods
+    let mut with_rep = terminfo.clone();
+    with_rep
+        .strings
+        .insert("rep", b"%p1%c\x1B[%p2%{1}%-%db".to_vec());
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), with_rep);
+    write!(term, "\x1B[{}mab", 31).unwrap();
+    term.hyperlink("http://a", "c").unwrap();
+    term.repeat_char(b'-', 3).unwrap();
+    term.expand_tabs("\t|").unwrap();
+    assert!(term.get_ref().ends_with(b"-\x1B[2b  |"));
+
+    // and tabs and backspaces written as text
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    write!(term, "a\tb").unwrap();
+    term.expand_tabs("\t|").unwrap();
+    write!(term, "\r\tabc\x08\x08").unwrap();
+    term.expand_tabs("\t|").unwrap();
+    assert_eq!(term.get_ref(), b"a\tb       |\r\tabc\x08\x08       |");
+
+    let mut terminfo = terminfo;
+    terminfo.numbers.insert("it", 4);
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(term.tab_width(), 4);
+    write!(term, "abcde").unwrap();
+    term.carriage_return().unwrap();
+    term.expand_tabs("ab\tc").unwrap();
+    assert_eq!(term.get_ref(), b"abcde\rab  c");
+}
+
+#[test]
+fn test_unsupported_callback() {
+    use std::sync::{Arc, Mutex};
+
+    let missing = Arc::new(Mutex::new(Vec::new()));
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    let log = missing.clone();
+    term.set_unsupported_callback(Box::new(move |cap| {
+        log.lock().unwrap().push(cap.to_owned())
+    }));
+
+    assert!(term.attr(term::Attr::Italic(true)).is_err());
+    assert!(term.fg(term::color::RED).is_err());
+    term.attr(term::Attr::Bold(true)).unwrap();
+    assert!(term.keypad_xmit().is_ok());
+    let mut clone = term.with_output(Vec::new());
+    assert!(clone.reset_palette().is_err());
+    assert_eq!(*missing.lock().unwrap(), ["sitm", "oc"]);
+}
+
+#[cfg(feature = "flate2")]
+#[test]
+fn test_gzip() {
+    use term::terminfo::Environment;
+
+    let terminfo = TermInfo::from_path("tests/gz/v/vt100.gz").unwrap();
+    assert_eq!(terminfo.names[0], "vt100");
+
+    let env = Environment {
+        term: Some("vt100".into()),
+        terminfo: Some("tests/gz".into()),
+        ..Environment::default()
+    };
+    let terminfo = TermInfo::from_environment(&env).unwrap();
+    assert_eq!(
+        terminfo.names,
+        TermInfo::from_path("tests/data/vt100").unwrap().names
+    );
+    assert_eq!(
+        term::terminfo::searcher::get_dbpath_for_term_in("vt100", &env).as_deref(),
+        Some(std::path::Path::new("tests/gz/v/vt100.gz"))
+    );
+}
+
+#[test]
+fn test_padding() {
+    let mut terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    assert_eq!(terminfo.strings["cuu1"], b"\x1B[A$<2>");
+    // no padding with flow control, or without a baud rate
+    let mut out = Vec::new();
+    terminfo
+        .apply_cap_with_padding("cuu1", &[], 9600, &mut out)
+        .unwrap();
+    terminfo
+        .apply_cap_with_padding("bel", &[], 9600, &mut out)
+        .unwrap();
+    assert_eq!(out, b"\x1B[A\x07");
+
+    terminfo.bools.remove("xon");
+    terminfo.numbers.insert("pb", 9600);
+    terminfo.strings.insert("cuu1", b"\x1B[A$<2>$<1/>".to_vec());
+    let mut out = Vec::new();
+    terminfo
+        .apply_cap_with_padding("cuu1", &[], 4800, &mut out)
+        .unwrap();
+    terminfo
+        .apply_cap_with_padding("cuu1", &[], 9600, &mut out)
+        .unwrap();
+    assert_eq!(out, b"\x1B[A\x1B[A\0\0\0");
+
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    term.cursor_up().unwrap();
+    term.set_baud_rate(Some(38400));
+    term.cursor_up().unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B[A\x1B[A\0\0\0\0\0\0\0\0\0\0\0\0");
+
+    // the pad character, or a delay without one
+    terminfo.strings.insert("pad", b"*".to_vec());
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    term.set_baud_rate(Some(9600));
+    term.cursor_up().unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B[A***");
+    terminfo.bools.insert("npc", true);
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_baud_rate(Some(9600));
+    term.cursor_up().unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B[A");
+}
+
+#[test]
+fn test_style_cache() {
+    use std::io::Write;
+    use term::{color, Attr};
+
+    let terminfo = TermInfo::from_path("tests/data/xterm-256color").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    // nothing is known before the first reset
+    term.fg(color::RED).unwrap();
+    term.fg(color::RED).unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B[31m\x1B[31m");
+
+    term.reset().unwrap();
+    term.fg(color::RED).unwrap();
+    term.fg(color::RED).unwrap();
+    term.set_colors(color::RED, color::BLUE).unwrap();
+    term.bg(color::BLUE).unwrap();
+    term.attr(Attr::Bold(true)).unwrap();
+    term.attr(Attr::Bold(true)).unwrap();
+    term.attr(Attr::Italic(false)).unwrap();
+    term.attr(Attr::Bold(false)).unwrap();
+    term.attr(Attr::Dim(false)).unwrap();
+    term.reset_fg().unwrap();
+    term.fg(color::RED).unwrap();
+    assert_eq!(
+        term.take_buffer(),
+        b"\x1B(B\x1B[m\x1B[31m\x1B[44m\x1B[1m\x1B[22m\x1B[39m\x1B[31m"
+    );
+
+    // escape sequences written as text invalidate the cache
+    write!(term, "\x1B[32m").unwrap();
+    term.fg(color::RED).unwrap();
+    term.reset().unwrap();
+    term.fg(color::RED).unwrap();
+    term.force_style_resync();
+    term.fg(color::RED).unwrap();
+    assert_eq!(
+        term.take_buffer(),
+        b"\x1B[32m\x1B[31m\x1B(B\x1B[m\x1B[31m\x1B[31m"
+    );
+}
+
+#[test]
+fn test_list_terminals() {
+    use term::terminfo::searcher::list_terminals_in;
+    use term::terminfo::Environment;
+
+    let env = Environment {
+        terminfo: Some("tests/db".into()),
+        terminfo_dirs: Some("tests/data/linux:tests/db:tests/missing".into()),
+        ..Environment::default()
+    };
+    let names = list_terminals_in(&env);
+    assert!(names.windows(2).all(|w| w[0] < w[1]));
+    for name in ["linux", "vt100", "xterm"] {
+        assert!(names.iter().any(|n| n == name), "{}", name);
+    }
+}
+
+#[test]
+fn test_bright_via_bold() {
+    use term::{color, Attr};
+
+    let terminfo = TermInfo::from_path("tests/data/xterm-256color").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.fg(color::BRIGHT_RED).unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B[91m");
+
+    term.set_bright_via_bold(true);
+    term.fg(color::BRIGHT_RED).unwrap();
+    term.set_colors(color::BRIGHT_GREEN, color::BRIGHT_BLUE)
+        .unwrap();
+    term.fg(color::RED).unwrap();
+    term.fg(200).unwrap();
+    assert_eq!(
+        term.take_buffer(),
+        b"\x1B[1m\x1B[31m\x1B[1m\x1B[32m\x1B[104m\x1B[22m\x1B[31m\x1B[38;5;200m"
+    );
+    assert!(term.supports_color_index(color::BRIGHT_RED));
+
+    // bold that was asked for stays on
+    term.attr(Attr::Bold(true)).unwrap();
+    term.fg(color::BRIGHT_RED).unwrap();
+    term.fg(color::RED).unwrap();
+    term.reset().unwrap();
+    term.fg(color::BRIGHT_RED).unwrap();
+    term.attr(Attr::Bold(true)).unwrap();
+    term.fg(color::RED).unwrap();
+    assert_eq!(
+        term.take_buffer(),
+        b"\x1B[1m\x1B[1m\x1B[31m\x1B[31m\x1B(B\x1B[m\x1B[1m\x1B[31m\x1B[31m"
+    );
+
+    // without a code for normal intensity, `sgr` sets the rest again
+    let mut terminfo = TermInfo::from_path("tests/data/xterm-256color").unwrap();
+    terminfo.names = vec!["custom".into()];
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo.clone());
+    term.set_bright_via_bold(true);
+    term.reset().unwrap();
+    term.attr(Attr::Underline(true)).unwrap();
+    term.bg(color::BLUE).unwrap();
+    term.fg(color::BRIGHT_RED).unwrap();
+    term.take_buffer();
+    term.fg(color::GREEN).unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B(B\x1B[0;4m\x1B[44m\x1B[32m");
+
+    // and without `sgr`, so does a reset
+    terminfo.strings.remove("sgr");
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_bright_via_bold(true);
+    term.reset().unwrap();
+    term.attr(Attr::Underline(true)).unwrap();
+    term.bg(color::BLUE).unwrap();
+    term.fg(color::BRIGHT_RED).unwrap();
+    term.take_buffer();
+    term.fg(color::GREEN).unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B(B\x1B[m\x1B[4m\x1B[44m\x1B[32m");
+
+    // turning bold off shows the normal color, so the bright one is set again
+    let terminfo = TermInfo::from_path("tests/data/xterm-256color").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_bright_via_bold(true);
+    term.reset().unwrap();
+    term.fg(color::BRIGHT_RED).unwrap();
+    term.attr(Attr::Bold(false)).unwrap();
+    term.fg(color::BRIGHT_RED).unwrap();
+    assert_eq!(
+        term.take_buffer(),
+        b"\x1B(B\x1B[m\x1B[1m\x1B[31m\x1B[22m\x1B[1m\x1B[31m"
+    );
+}
+
+#[test]
+fn test_set_ansi_colors() {
+    use term::color;
+
+    let terminfo = TermInfo::from_path("tests/data/dumb").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert!(!term.supports_color());
+
+    term.set_ansi_colors(8);
+    assert!(term.supports_color());
+    term.fg(color::RED).unwrap();
+    term.bg(color::BRIGHT_BLUE).unwrap();
+    term.reset().unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B[31m\x1B[44m\x1B[39;49m");
+
+    term.set_ansi_colors(256);
+    term.bg(color::BRIGHT_BLUE).unwrap();
+    term.fg(200).unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B[104m\x1B[38;5;200m");
+}
+
+#[test]
+fn test_color_256() {
+    use term::color;
+
+    let terminfo = TermInfo::from_path("tests/data/xterm-256color").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_bright_via_bold(true);
+    term.fg_256(9).unwrap();
+    term.bg_256(200).unwrap();
+    term.fg(color::BRIGHT_RED).unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B[91m\x1B[48;5;200m\x1B[1m\x1B[31m");
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert_eq!(term.fg_256(9), Err(term::Error::ColorOutOfRange));
+    assert_eq!(term.get_ref(), b"");
+}
+
+#[test]
+fn test_line_drawing() {
+    use term::terminfo::{Environment, LineChar};
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert!(!term.prefers_unicode_lines());
+    assert_eq!(term.line_char(LineChar::Horizontal), 'q');
+    assert_eq!(term.line_char(LineChar::TopLeft), 'l');
+    term.enable_line_drawing().unwrap();
+    term.disable_line_drawing().unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B(0\x1B(B");
+
+    let terminfo = TermInfo::from_path("tests/data/dumb").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_environment(Environment {
+        lc_ctype: Some(String::new()),
+        lang: Some("en_US.UTF-8".into()),
+        ..Default::default()
+    });
+    assert!(term.prefers_unicode_lines());
+    assert_eq!(term.line_char(LineChar::Horizontal), '\u{2500}');
+    term.set_environment(Environment {
+        lc_all: Some("C".into()),
+        lang: Some("en_US.UTF-8".into()),
+        ..Default::default()
+    });
+    assert!(!term.prefers_unicode_lines());
+    assert_eq!(term.line_char(LineChar::Cross), '+');
+    term.enable_line_drawing().unwrap();
+    assert_eq!(term.get_ref(), b"");
+}
+
+#[test]
+fn test_buffer_capacity() {
+    use std::io::Write;
+    use term::color;
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_buffer_capacity(16);
+    term.fg(color::RED).unwrap();
+    write!(term, "hi").unwrap();
+    assert_eq!(term.get_ref(), b"");
+    term.present().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[31mhi");
+
+    write!(term, "0123456789abcdef").unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[31mhi0123456789abcdef");
+
+    // Queries write out what's pending before waiting for the reply.
+    write!(term, "x").unwrap();
+    term.query_background(&mut &b""[..]).unwrap();
+    assert!(term.get_ref().ends_with(b"x\x1B]11;?\x1B\\"));
+
+    write!(term, "end").unwrap();
+    assert!(term.into_inner().ends_with(b"end"));
+}
+
+#[test]
+fn test_init_charset() {
+    let terminfo = TermInfo::from_path("tests/data/vt100").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.enable_line_drawing().unwrap();
+    term.disable_line_drawing().unwrap();
+    term.enable_line_drawing().unwrap();
+    assert_eq!(term.take_buffer(), b"\x1B(B\x1B)0\x0E\x0F\x0E");
+    assert!(term.init_charset().unwrap());
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    assert!(!term.init_charset().unwrap());
+    assert_eq!(term.get_ref(), b"");
+}
+
+#[test]
+fn test_term_program() {
+    use term::terminfo::{Environment, TermProgram, Version};
+
+    assert!(Version::parse("3.10") > Version::parse("3.9"));
+    assert_eq!(Version::parse("3.4.0"), Version::parse("3.4"));
+    assert_eq!(
+        Version::parse("20230712-072601-f4abf8fd"),
+        Version::parse("20230712")
+    );
+    assert_eq!(Version::parse("1.85.1-insider"), Version::parse("1.85.1"));
+    assert_eq!(Version::parse("abc"), None);
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_environment(Environment {
+        term_program: Some("iTerm.app".into()),
+        term_program_version: Some("3.4.19".into()),
+        ..Default::default()
+    });
+    assert_eq!(term.term_program(), Some(TermProgram::ITerm));
+    assert!(term.term_program_version() >= Version::parse("3.4"));
+    term.set_environment(Environment {
+        term_program: Some("Unknown".into()),
+        ..Default::default()
+    });
+    assert_eq!(
+        term.term_program(),
+        Some(TermProgram::Other("Unknown".to_owned()))
+    );
+    term.set_environment(Environment::default());
+    assert_eq!(term.term_program(), None);
+    assert_eq!(term.term_program_version(), None);
+}
+
+#[test]
+fn test_auto_flush_attrs() {
+    use std::io::Write;
+    use term::{color, Attr};
+
+    let terminfo = TermInfo::from_path("tests/data/xterm").unwrap();
+    let mut term = TerminfoTerminal::new_with_terminfo(Vec::new(), terminfo);
+    term.set_buffer_capacity(64);
+    term.fg(color::RED).unwrap();
+    assert_eq!(term.get_ref(), b"");
+
+    term.set_auto_flush_attrs(true);
+    term.attr(Attr::Bold(true)).unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[31m\x1B[1m");
+    write!(term, "hi").unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[31m\x1B[1m");
+    term.reset().unwrap();
+    assert_eq!(term.get_ref(), b"\x1B[31m\x1B[1mhi\x1B(B\x1B[m");
+}